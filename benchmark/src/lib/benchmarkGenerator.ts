@@ -83,7 +83,7 @@ export const generateBenchmarks = ({
     title: 'Execute a search query',
     executor: (state) => {
       const res = state.searchIndex.search('the', {fields: ['id', 'title', 'body'], limit: 10});
-      return `Found ${res.length} documents matching the query`;
+      return `Found ${res.hits.length} documents matching the query`;
     }
   }];
 
@@ -0,0 +1,110 @@
+use tantivy::tokenizer::{BoxTokenStream, Token, Tokenizer};
+
+use crate::tokenizers::VecTokenStream;
+
+// Splits the runs of CJK characters into unigrams and overlapping bigrams, which doesn't require any dictionary:
+// `東京都` gives `東`, `東京`, `京`, `京都` and `都`. A bigram has the position of its first character, so that
+// a query tokenized the same way matches as a phrase, and a single-character query matches the words containing it.
+// The other alphanumeric runs are kept as whole tokens, like the `SimpleTokenizer` does.
+#[derive(Clone)]
+pub struct CjkTokenizer;
+
+// Han ideographs, Hiragana, Katakana and Hangul
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{1100}'..='\u{11FF}'
+        | '\u{3040}'..='\u{30FF}'
+        | '\u{3130}'..='\u{318F}'
+        | '\u{31F0}'..='\u{31FF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{AC00}'..='\u{D7AF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{FF66}'..='\u{FF9D}'
+        | '\u{20000}'..='\u{2A6DF}'
+        | '\u{2A700}'..='\u{2CEAF}'
+    )
+}
+
+fn push_token(tokens: &mut Vec<Token>, text: &str, offset_from: usize, offset_to: usize, position: usize, position_length: usize) {
+    tokens.push(Token {
+        offset_from,
+        offset_to,
+        position,
+        text: text[offset_from..offset_to].to_string(),
+        position_length,
+    });
+}
+
+// Returns the position following the run
+fn push_cjk_run(tokens: &mut Vec<Token>, text: &str, run: &[(usize, char)], position: usize) -> usize {
+    for (index, (offset, c)) in run.iter().enumerate() {
+        push_token(tokens, text, *offset, offset + c.len_utf8(), position + index, 1);
+        if let Some((next_offset, next_char)) = run.get(index + 1) {
+            push_token(tokens, text, *offset, next_offset + next_char.len_utf8(), position + index, 2);
+        }
+    }
+    position + run.len()
+}
+
+impl Tokenizer for CjkTokenizer {
+    fn token_stream<'a>(&self, text: &'a str) -> BoxTokenStream<'a> {
+        let mut tokens = Vec::new();
+        let mut position = 0;
+        let mut cjk_run: Vec<(usize, char)> = Vec::new();
+        let mut word_start: Option<usize> = None;
+        for (offset, c) in text.char_indices() {
+            if is_cjk(c) {
+                if let Some(start) = word_start.take() {
+                    push_token(&mut tokens, text, start, offset, position, 1);
+                    position += 1;
+                }
+                cjk_run.push((offset, c));
+                continue;
+            }
+            position = push_cjk_run(&mut tokens, text, &cjk_run, position);
+            cjk_run.clear();
+            if c.is_alphanumeric() {
+                word_start.get_or_insert(offset);
+            } else if let Some(start) = word_start.take() {
+                push_token(&mut tokens, text, start, offset, position, 1);
+                position += 1;
+            }
+        }
+        position = push_cjk_run(&mut tokens, text, &cjk_run, position);
+        if let Some(start) = word_start {
+            push_token(&mut tokens, text, start, text.len(), position, 1);
+        }
+        BoxTokenStream::from(VecTokenStream::from(tokens))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::tokenizer::{TokenStream, Tokenizer};
+
+    use super::CjkTokenizer;
+
+    fn tokens(text: &str) -> Vec<(String, usize, usize, usize)> {
+        let mut tokens = Vec::new();
+        CjkTokenizer.token_stream(text).process(&mut |token| {
+            tokens.push((token.text.clone(), token.position, token.offset_from, token.offset_to));
+        });
+        tokens
+    }
+
+    #[test]
+    fn unigrams_and_bigrams() {
+        let token = |text: &str, position, offset_from, offset_to| (text.to_string(), position, offset_from, offset_to);
+        assert_eq!(vec![
+            token("東", 0, 0, 3), token("東京", 0, 0, 6), token("京", 1, 3, 6), token("京都", 1, 3, 9), token("都", 2, 6, 9),
+            token("Tokyo", 3, 10, 15),
+        ], tokens("東京都 Tokyo"));
+        assert_eq!(vec![
+            token("iPhone", 0, 0, 6), token("を", 1, 6, 9), token("を買", 1, 6, 12), token("買", 2, 9, 12), token("買う", 2, 9, 15), token("う", 3, 12, 15),
+        ], tokens("iPhoneを買う"));
+        assert_eq!(vec![token("第", 0, 0, 3), token("1", 1, 3, 4), token("章", 2, 4, 7)], tokens("第1章"));
+        assert_eq!(vec![token("한", 0, 0, 3), token("한국", 0, 0, 6), token("국", 1, 3, 6), token("국어", 1, 3, 9), token("어", 2, 6, 9)], tokens("한국어!"));
+        assert!(tokens("  ").is_empty());
+    }
+}
@@ -0,0 +1,180 @@
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use tantivy::{
+    collector::{Collector, SegmentCollector},
+    DocAddress, DocId, Score, Searcher, SegmentOrdinal, SegmentReader,
+};
+
+use crate::errors::WasmInterfaceError;
+
+// Position of a hit in the ranking, sent to JS as an opaque token to resume a search after this hit.
+// The segment is identified by its id because segment ordinals change when segments are registered or removed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+    pub score: Score,
+    pub segment_id: String,
+    pub doc_id: DocId,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        format!("{:08x}.{}.{:x}", self.score.to_bits(), self.segment_id, self.doc_id)
+    }
+
+    pub fn decode(token: &str) -> Result<Cursor, WasmInterfaceError> {
+        let invalid = || WasmInterfaceError::InvalidCursor(token.to_string());
+        let mut parts = token.split('.');
+        let (score, segment_id, doc_id) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(score), Some(segment_id), Some(doc_id), None) => (score, segment_id, doc_id),
+            _ => return Err(invalid()),
+        };
+        Ok(Cursor {
+            score: Score::from_bits(u32::from_str_radix(score, 16).map_err(|_err| invalid())?),
+            segment_id: segment_id.to_string(),
+            doc_id: DocId::from_str_radix(doc_id, 16).map_err(|_err| invalid())?,
+        })
+    }
+}
+
+// A hit ordered by rank: the best hit (highest score, then lowest address like `TopDocs`) is the smallest.
+#[derive(Debug, Clone, Copy)]
+struct RankedDoc {
+    score: Score,
+    address: DocAddress,
+}
+
+impl Ord for RankedDoc {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.score.partial_cmp(&self.score)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| self.address.cmp(&other.address))
+    }
+}
+
+impl PartialOrd for RankedDoc {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for RankedDoc {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RankedDoc {}
+
+// Collects the top hits ranked after a cursor, like `TopDocs` would return them on the next pages.
+pub struct SearchAfter {
+    after: RankedDoc,
+    // false when the segment of the cursor is not registered anymore: only lower scores can come after it then
+    after_segment_found: bool,
+    limit: usize,
+    offset: usize,
+}
+
+impl SearchAfter {
+    pub fn new(cursor: &Cursor, searcher: &Searcher, limit: usize, offset: usize) -> SearchAfter {
+        let segment_ord = searcher.segment_readers()
+            .iter()
+            .position(|segment_reader| segment_reader.segment_id().uuid_string() == cursor.segment_id);
+        SearchAfter {
+            after: RankedDoc {
+                score: cursor.score,
+                address: DocAddress::new(segment_ord.unwrap_or_default() as SegmentOrdinal, cursor.doc_id),
+            },
+            after_segment_found: segment_ord.is_some(),
+            limit,
+            offset,
+        }
+    }
+}
+
+impl Collector for SearchAfter {
+    type Fruit = Vec<(Score, DocAddress)>;
+    type Child = SearchAfterSegmentCollector;
+
+    fn for_segment(&self, segment_local_id: SegmentOrdinal, _segment: &SegmentReader) -> tantivy::Result<Self::Child> {
+        Ok(SearchAfterSegmentCollector {
+            segment_ord: segment_local_id,
+            after: self.after,
+            after_segment_found: self.after_segment_found,
+            heap: BinaryHeap::with_capacity(self.limit + self.offset),
+            capacity: self.limit + self.offset,
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        true
+    }
+
+    fn merge_fruits(&self, segment_fruits: Vec<Vec<(Score, DocAddress)>>) -> tantivy::Result<Self::Fruit> {
+        let mut ranked_docs: Vec<RankedDoc> = segment_fruits.into_iter()
+            .flatten()
+            .map(|(score, address)| RankedDoc { score, address })
+            .collect();
+        ranked_docs.sort();
+        Ok(ranked_docs.into_iter()
+            .skip(self.offset)
+            .take(self.limit)
+            .map(|ranked_doc| (ranked_doc.score, ranked_doc.address))
+            .collect())
+    }
+}
+
+pub struct SearchAfterSegmentCollector {
+    segment_ord: SegmentOrdinal,
+    after: RankedDoc,
+    after_segment_found: bool,
+    // the worst collected hit is at the top of the heap
+    heap: BinaryHeap<RankedDoc>,
+    capacity: usize,
+}
+
+impl SegmentCollector for SearchAfterSegmentCollector {
+    type Fruit = Vec<(Score, DocAddress)>;
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        let ranked_doc = RankedDoc { score, address: DocAddress::new(self.segment_ord, doc) };
+        let is_after = if self.after_segment_found {
+            ranked_doc > self.after
+        } else {
+            score < self.after.score
+        };
+        if !is_after || self.capacity == 0 {
+            return;
+        }
+        if self.heap.len() < self.capacity {
+            self.heap.push(ranked_doc);
+        } else if let Some(mut worst) = self.heap.peek_mut() {
+            if ranked_doc < *worst {
+                *worst = ranked_doc;
+            }
+        }
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        self.heap.into_sorted_vec()
+            .into_iter()
+            .map(|ranked_doc| (ranked_doc.score, ranked_doc.address))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cursor;
+
+    #[test]
+    fn encode_and_decode() {
+        let cursor = Cursor {
+            score: 1.25,
+            segment_id: "0f4e6cbfc5ab4d3d8f3d3b7c1b2a9e10".to_string(),
+            doc_id: 42,
+        };
+        assert_eq!(cursor, Cursor::decode(&cursor.encode()).unwrap());
+        assert!(Cursor::decode("not a cursor").is_err());
+        assert!(Cursor::decode("3fa00000.abc").is_err());
+    }
+}
@@ -0,0 +1,59 @@
+use tantivy::{
+    query::{EmptyScorer, Explanation, Query, Scorer, Weight},
+    DocId, DocSet, Score, Searcher, SegmentId, SegmentReader, TantivyError, TERMINATED,
+};
+
+// Matches a single document of a segment, e.g. to exclude it from the hits of another query with a `MustNot` clause
+#[derive(Clone, Debug)]
+pub struct DocQuery {
+    pub segment_id: SegmentId,
+    pub doc_id: DocId,
+}
+
+impl Query for DocQuery {
+    fn weight(&self, _searcher: &Searcher, _scoring_enabled: bool) -> tantivy::Result<Box<dyn Weight>> {
+        Ok(Box::new(self.clone()))
+    }
+}
+
+impl Weight for DocQuery {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> tantivy::Result<Box<dyn Scorer>> {
+        if reader.segment_id() != self.segment_id || self.doc_id >= reader.max_doc() {
+            return Ok(Box::new(EmptyScorer));
+        }
+        Ok(Box::new(DocScorer { doc: self.doc_id, score: boost }))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> tantivy::Result<Explanation> {
+        if reader.segment_id() != self.segment_id || doc != self.doc_id {
+            return Err(TantivyError::InvalidArgument(format!("Document #({}) does not match", doc)));
+        }
+        Ok(Explanation::new("DocQuery", 1.0))
+    }
+}
+
+struct DocScorer {
+    doc: DocId,
+    score: Score,
+}
+
+impl DocSet for DocScorer {
+    fn advance(&mut self) -> DocId {
+        self.doc = TERMINATED;
+        self.doc
+    }
+
+    fn doc(&self) -> DocId {
+        self.doc
+    }
+
+    fn size_hint(&self) -> u32 {
+        1
+    }
+}
+
+impl Scorer for DocScorer {
+    fn score(&mut self) -> Score {
+        self.score
+    }
+}
@@ -5,10 +5,67 @@ use thiserror::Error;
 pub enum WasmInterfaceError {
     #[error("The field `{0}` doesn't exist in schema")]
     InvalidField(String),
+
+    #[error("The value `{1}` is not valid for the field `{0}`")]
+    InvalidFieldValue(String, String),
+
+    #[error("Invalid options for the field `{0}`: {1}")]
+    InvalidFieldOptions(String, String),
+
+    #[error("The field `{0}` is a fast field, it can only hold one value per document")]
+    MultipleValuesInFastField(String),
+
+    #[error("The field `{0}` has a type that cannot be set from a document")]
+    UnsupportedFieldType(String),
     
     #[error("The directory is empty")]
     EmptyDirectory,
 
+    #[error("The tokenizer `{1}` of the field `{0}` doesn't exist")]
+    UnknownTokenizer(String, String),
+
+    #[error("Invalid analyzer `{0}`: {1}")]
+    InvalidAnalyzer(String, String),
+
+    #[error("The tokenizer name `{0}` is already taken")]
+    TokenizerNameTaken(String),
+
+    #[error("The tokenizer `{0}` failed: {1}")]
+    TokenizerFailed(String, String),
+
+    #[error("The analyzer `{0}` is defined differently in the registered segments")]
+    ConflictingAnalyzer(String),
+
+    #[error("The field `{0}` is not a text field")]
+    NotATextField(String),
+
+    #[error("The field `{0}` is not indexed with positions, phrase queries are not supported on it")]
+    NoPositions(String),
+
+    #[error("The range on the field `{0}` has two lower or two upper bounds")]
+    InvalidRange(String),
+
+    #[error("The pattern on the field `{0}` is invalid: {1}")]
+    InvalidPattern(String, String),
+
+    #[error("The fuzzy distance {0} is too large, the maximum is 2")]
+    InvalidFuzzyDistance(u8),
+
+    #[error("The field `{0}` must be a numeric or date fast field to sort by it")]
+    NotSortable(String),
+
+    #[error("The field `{0}` must be a stored text field to be highlighted")]
+    NotHighlightable(String),
+
+    #[error("The option `{0}` cannot be combined with the option `{1}`")]
+    IncompatibleOptions(String, String),
+
+    #[error("The cursor `{0}` is invalid")]
+    InvalidCursor(String),
+
+    #[error("The document `{1}` of the segment `{0}` is not in the index")]
+    InvalidHitRef(String, u32),
+
     #[error("Failed to serialize directory")]
     FailedToSerializeDirectory,
     #[error("Failed to create archive root")]
@@ -0,0 +1,131 @@
+use tantivy::schema::{
+    DateOptions,
+    DatePrecision,
+    FacetOptions,
+    IndexRecordOption,
+    NumericOptions,
+    TextFieldIndexing,
+    TextOptions,
+    FAST,
+    INDEXED,
+    STRING,
+    STORED,
+    TEXT,
+};
+
+use crate::errors::WasmInterfaceError;
+
+// The boolean flags a field can be declared with, shared by the `SegmentBuilder` schema and the legacy `Schema`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FieldFlags {
+    pub fast: Option<bool>,
+    pub indexed: Option<bool>,
+    pub string: Option<bool>,
+    pub stored: Option<bool>,
+    pub text: Option<bool>,
+}
+
+fn invalid(field_name: &str, reason: &str) -> WasmInterfaceError {
+    WasmInterfaceError::InvalidFieldOptions(field_name.to_string(), reason.to_string())
+}
+
+// `tokenizer` replaces the default tokenizer of a `text` field.
+pub fn text_options(field_name: &str, flags: FieldFlags, tokenizer: Option<&str>) -> Result<TextOptions, WasmInterfaceError> {
+    let string = flags.string.unwrap_or_default();
+    let text = flags.text.unwrap_or_default();
+    let fast = flags.fast.unwrap_or_default();
+
+    if string && text {
+        return Err(invalid(field_name, "a field cannot be both `string` and `text`"));
+    }
+    if flags.indexed == Some(false) && (string || text) {
+        return Err(invalid(field_name, "`string` and `text` fields are always indexed"));
+    }
+    if fast && text {
+        return Err(invalid(field_name, "`fast` is not supported on tokenized `text` fields, use a `string` field instead"));
+    }
+    if tokenizer.is_some() && !text {
+        return Err(invalid(field_name, "`tokenizer` only applies to `text` fields"));
+    }
+
+    let mut field_option = TextOptions::default();
+    // `indexed` alone indexes the value untokenized, like `string`
+    if string || (flags.indexed.unwrap_or_default() && !text) {
+        field_option = field_option | STRING;
+    }
+    if text {
+        field_option = field_option | TEXT;
+        if let Some(tokenizer) = tokenizer {
+            let indexing = TextFieldIndexing::default()
+                .set_tokenizer(tokenizer)
+                .set_index_option(IndexRecordOption::WithFreqsAndPositions);
+            field_option = field_option.set_indexing_options(indexing);
+        }
+    }
+    if fast {
+        if field_option.get_indexing_options().is_none() {
+            return Err(invalid(field_name, "`fast` text fields must be `string` or `indexed`"));
+        }
+        field_option = field_option | FAST;
+    }
+    if flags.stored.unwrap_or_default() {
+        field_option = field_option | STORED;
+    }
+    Ok(field_option)
+}
+
+pub fn numeric_options(field_name: &str, flags: FieldFlags) -> Result<NumericOptions, WasmInterfaceError> {
+    if flags.string.unwrap_or_default() || flags.text.unwrap_or_default() {
+        return Err(invalid(field_name, "`string` and `text` only apply to text fields"));
+    }
+
+    let mut field_option = NumericOptions::default();
+    // numeric fields are indexed unless told otherwise so that they can be used in term and range queries
+    if flags.indexed.unwrap_or(true) {
+        field_option = field_option | INDEXED;
+    }
+    if flags.fast.unwrap_or_default() {
+        field_option = field_option | FAST;
+    }
+    if flags.stored.unwrap_or_default() {
+        field_option = field_option | STORED;
+    }
+    Ok(field_option)
+}
+
+pub fn date_options(field_name: &str, flags: FieldFlags) -> Result<DateOptions, WasmInterfaceError> {
+    if flags.string.unwrap_or_default() || flags.text.unwrap_or_default() {
+        return Err(invalid(field_name, "`string` and `text` only apply to text fields"));
+    }
+
+    // JS dates have a millisecond precision, there's no need to keep more
+    let mut field_option = DateOptions::default().set_precision(DatePrecision::Milliseconds);
+    if flags.indexed.unwrap_or(true) {
+        field_option = field_option | INDEXED;
+    }
+    if flags.fast.unwrap_or_default() {
+        field_option = field_option | FAST;
+    }
+    if flags.stored.unwrap_or_default() {
+        field_option = field_option | STORED;
+    }
+    Ok(field_option)
+}
+
+pub fn facet_options(field_name: &str, flags: FieldFlags) -> Result<FacetOptions, WasmInterfaceError> {
+    if flags.string.unwrap_or_default() || flags.text.unwrap_or_default() {
+        return Err(invalid(field_name, "`string` and `text` only apply to text fields"));
+    }
+    if flags.indexed == Some(false) {
+        return Err(invalid(field_name, "facet fields are always indexed"));
+    }
+    if flags.fast.unwrap_or_default() {
+        return Err(invalid(field_name, "`fast` is not supported on facet fields"));
+    }
+
+    let mut field_option = FacetOptions::default();
+    if flags.stored.unwrap_or_default() {
+        field_option = field_option.set_stored();
+    }
+    Ok(field_option)
+}
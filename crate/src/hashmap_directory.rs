@@ -55,6 +55,11 @@ fn to_hex_string(a: &[u8]) -> String{
 
 
 
+// Files describing the whole index rather than a segment, they are merged by the `SearchIndex` instead of being copied
+fn is_index_file(path: &Path) -> bool {
+    path == Path::new("meta.json") || path == Path::new("analyzers.json")
+}
+
 impl HashMapDirectory {
     pub fn new()-> Self {
         HashMapDirectory(Arc::new(Mutex::new(HashMap::new())))
@@ -66,7 +71,7 @@ impl HashMapDirectory {
         let mut self_inner_map = self.0.lock().expect("Taking the lock should always work");
 
         for (path, file) in remote_directory_inner_map {
-            if path != Path::new("meta.json") {
+            if !is_index_file(&path) {
                 self_inner_map.insert(path, file);
             }
         }
@@ -77,7 +82,7 @@ impl HashMapDirectory {
         let mut self_inner_map = self.0.lock().expect("Taking the lock should always work");
 
         for (path, _) in remote_directory_inner_map.iter() {
-            if path != Path::new("meta.json") {
+            if !is_index_file(path) {
                 self_inner_map.remove(path);
             }
         }
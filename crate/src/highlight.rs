@@ -0,0 +1,116 @@
+use std::{collections::HashMap, ops::Range};
+
+use serde::{Serialize, Deserialize};
+
+use tantivy::{
+    query::Query,
+    schema::{Document as TantivyDocument, FieldType},
+    Searcher, SnippetGenerator,
+};
+
+use crate::errors::WasmInterfaceError;
+use crate::utils::utf16_len;
+
+const DEFAULT_PRE_TAG: &str = "<b>";
+const DEFAULT_POST_TAG: &str = "</b>";
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct HighlightOption {
+    pub fields: Vec<String>,
+    pub max_chars: Option<usize>,
+    pub pre_tag: Option<String>,
+    pub post_tag: Option<String>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct HighlightRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Highlight {
+    // the fragment with the HTML special characters escaped and the matched terms surrounded by the tags
+    pub html: String,
+    pub fragment: String,
+    // ranges of the matched terms in `fragment`
+    pub ranges: Vec<HighlightRange>,
+}
+
+pub struct Highlighter {
+    generators: Vec<(String, SnippetGenerator)>,
+    pre_tag: String,
+    post_tag: String,
+}
+
+impl Highlighter {
+    pub fn new(searcher: &Searcher, query: &dyn Query, option: &HighlightOption) -> Result<Highlighter, String> {
+        let schema = searcher.schema();
+        let mut generators = Vec::new();
+        for field_name in option.fields.iter() {
+            let field = schema.get_field(field_name).ok_or_else(||{WasmInterfaceError::InvalidField(field_name.to_owned()).to_string()})?;
+            let field_entry = schema.get_field_entry(field);
+            if !matches!(field_entry.field_type(), FieldType::Str(_)) || !field_entry.is_stored() {
+                return Err(WasmInterfaceError::NotHighlightable(field_name.to_owned()).to_string());
+            }
+            let mut generator = SnippetGenerator::create(searcher, query, field).map_err(|err| err.to_string())?;
+            if let Some(max_chars) = option.max_chars {
+                generator.set_max_num_chars(max_chars);
+            }
+            generators.push((field_name.to_owned(), generator));
+        }
+        Ok(Highlighter {
+            generators,
+            pre_tag: option.pre_tag.clone().unwrap_or_else(|| DEFAULT_PRE_TAG.to_string()),
+            post_tag: option.post_tag.clone().unwrap_or_else(|| DEFAULT_POST_TAG.to_string()),
+        })
+    }
+
+    pub fn highlight(&self, doc: &TantivyDocument) -> HashMap<String, Highlight> {
+        self.generators.iter().map(|(field_name, generator)|{
+            let snippet = generator.snippet_from_doc(doc);
+            let fragment = snippet.fragment();
+            let highlight = Highlight {
+                html: self.to_html(fragment, snippet.highlighted()),
+                fragment: fragment.to_string(),
+                ranges: snippet.highlighted().iter().map(|range|{
+                    HighlightRange {
+                        start: utf16_len(&fragment[..range.start]),
+                        end: utf16_len(&fragment[..range.end]),
+                    }
+                }).collect(),
+            };
+            (field_name.clone(), highlight)
+        }).collect()
+    }
+
+    fn to_html(&self, fragment: &str, highlighted: &[Range<usize>]) -> String {
+        let mut html = String::with_capacity(fragment.len());
+        let mut start_from = 0;
+        for range in highlighted {
+            html.push_str(&escape_html(&fragment[start_from..range.start]));
+            html.push_str(&self.pre_tag);
+            html.push_str(&escape_html(&fragment[range.clone()]));
+            html.push_str(&self.post_tag);
+            start_from = range.end;
+        }
+        html.push_str(&escape_html(&fragment[start_from..]));
+        html
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
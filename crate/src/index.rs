@@ -6,12 +6,6 @@ use tantivy::{
     schema::{
         Schema as TantivySchema,
         Document as TantivyDocument,
-        FAST,
-        INDEXED,
-        STRING,
-        STORED,
-        TEXT,
-        TextOptions,
     },
     DocAddress,
     Index as TantivyIndex,
@@ -26,6 +20,8 @@ use serde::{Serialize as SerdeSerialize, Deserialize as SerializeDeserialize};
 use bytecheck::CheckBytes;
 
 use super::hashmap_directory::{HashMapDirectory, SerializableHashMapDirectory};
+use super::errors::WasmInterfaceError;
+use super::field_options::{FieldFlags, text_options};
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen]
@@ -67,33 +63,21 @@ impl Schema {
     }
 
 
-    fn build_schema(&self)-> TantivySchema{
+    fn build_schema(&self)-> Result<TantivySchema, WasmInterfaceError>{
         trace!("build_schema {}", "");
         let mut schema_builder = TantivySchema::builder();
         for (field_name, option) in self.fields.iter(){
-            
-            // TODO implement the field options in a way that makes sense
-            let mut field_option = TextOptions::default();
-            // if option.fast.unwrap_or_default() {
-                // field_option = field_option | FAST;
-            // }
-            // if option.indexed.unwrap_or_default() {
-                // field_option = field_option | INDEXED;
-            // }
-            if option.string.unwrap_or_default() {
-                field_option = field_option | STRING;
-            }
-            if option.stored.unwrap_or_default() {
-                field_option = field_option | STORED;
-            }
-            if option.text.unwrap_or_default() {
-                field_option = field_option | TEXT;
-            }
-
-            schema_builder.add_text_field(field_name, field_option);
+            let flags = FieldFlags {
+                fast: option.fast,
+                indexed: option.indexed,
+                string: option.string,
+                stored: option.stored,
+                text: option.text,
+            };
+            schema_builder.add_text_field(field_name, text_options(field_name, flags, None)?);
         }
         let schema = schema_builder.build();
-        schema
+        Ok(schema)
     }
 
     #[wasm_bindgen(js_name = "getFields")]
@@ -122,24 +106,31 @@ pub struct Index{
 impl Index {
 
     #[wasm_bindgen(js_name = "parseSerializedIndex")]
-    pub fn parse_serialized_index(serialized_index: &[u8])-> Index{
+    pub fn parse_serialized_index(serialized_index: &[u8])-> Result<Index, String>{
         let archived = rkyv::check_archived_root::<SerializableIndex>(serialized_index).unwrap();
         let SerializableIndex{schema, directory} = archived.deserialize(&mut rkyv::Infallible).unwrap();
         let directory: HashMapDirectory = directory.into();
 
-        let tantivy_schema = schema.build_schema();
-        let tantivy_index = TantivyIndex::builder().schema(tantivy_schema.clone()).open_or_create(directory.clone()).unwrap();
-        Index { tantivy_index, tantivy_schema, schema, directory}
+        // an existing index keeps the tantivy schema it was created with: the `fast` and `indexed` flags
+        // were ignored before, rebuilding the schema from them would not match the one of older indexes
+        let tantivy_index = if TantivyIndex::exists(&directory).map_err(|err| err.to_string())? {
+            TantivyIndex::open(directory.clone()).map_err(|err| err.to_string())?
+        } else {
+            let tantivy_schema = schema.build_schema().map_err(|err| err.to_string())?;
+            TantivyIndex::builder().schema(tantivy_schema).open_or_create(directory.clone()).map_err(|err| err.to_string())?
+        };
+        let tantivy_schema = tantivy_index.schema();
+        Ok(Index { tantivy_index, tantivy_schema, schema, directory})
     }
 
     #[wasm_bindgen(js_name = "fromSchema")]
-    pub fn from_schema(schema: Schema) -> Index{
+    pub fn from_schema(schema: Schema) -> Result<Index, String>{
         let directory= HashMapDirectory::new();
-        let tantivy_schema = schema.build_schema();
+        let tantivy_schema = schema.build_schema().map_err(|err| err.to_string())?;
         trace!("createIndexFromSchema");
-        let tantivy_index = TantivyIndex::builder().schema(tantivy_schema.clone()).open_or_create(directory.clone()).unwrap();
+        let tantivy_index = TantivyIndex::builder().schema(tantivy_schema.clone()).open_or_create(directory.clone()).map_err(|err| err.to_string())?;
 
-        Index { tantivy_index, tantivy_schema, schema, directory }
+        Ok(Index { tantivy_index, tantivy_schema, schema, directory })
     }
 
     #[wasm_bindgen(js_name = "serializeIndex")]
@@ -258,4 +249,43 @@ impl Document{
     pub fn add_text(&mut self, field: &str, data: &str){
         self.texts.push((field.to_string(), data.to_string()));
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use common_macros::hash_map;
+    use tantivy::{
+        schema::{Schema as TantivySchema, TextOptions, STORED, STRING},
+        Index as TantivyIndex,
+    };
+
+    use crate::hashmap_directory::HashMapDirectory;
+    use super::{Document, FieldOption, Index, Schema};
+
+    #[test]
+    fn parse_index_serialized_with_ignored_flags() {
+        let schema = Schema {
+            fields: hash_map! {
+                "id".to_string() => FieldOption{fast: Some(true), indexed: None, string: Some(true), stored: Some(true), text: None},
+                "folder".to_string() => FieldOption{fast: None, indexed: Some(true), string: None, stored: None, text: None},
+            },
+        };
+        // the tantivy schema built when `fast` and `indexed` were ignored
+        let mut schema_builder = TantivySchema::builder();
+        schema_builder.add_text_field("id", STRING | STORED);
+        schema_builder.add_text_field("folder", TextOptions::default());
+        let tantivy_schema = schema_builder.build();
+        let directory = HashMapDirectory::new();
+        let tantivy_index = TantivyIndex::builder().schema(tantivy_schema.clone()).open_or_create(directory.clone()).unwrap();
+
+        let mut writer = Index { tantivy_index, tantivy_schema, schema, directory }.writer(50_000_000);
+        let mut doc = Document::new();
+        doc.add_text("id", "42");
+        doc.add_text("folder", "inbox");
+        writer.add_document(doc);
+        let serialized_index = writer.commit().serialize_index();
+
+        let index = Index::parse_serialized_index(&serialized_index).unwrap();
+        assert!(index.search("id:42").contains("42"));
+    }
+}
@@ -0,0 +1,162 @@
+use std::{cell::RefCell, collections::HashMap};
+
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+use js_sys::Function;
+
+use log::error;
+
+use tantivy::tokenizer::{BoxTokenStream, Token, Tokenizer};
+
+use crate::errors::WasmInterfaceError;
+use crate::tokenizers::{is_builtin_tokenizer, VecTokenStream};
+
+// JS functions can't be shared between threads: the tokenizer only holds the name of its function,
+// looked up in this registry when a text is tokenized. Wasm runs on a single thread.
+thread_local! {
+    static JS_TOKENIZERS: RefCell<HashMap<String, Function>> = RefCell::new(HashMap::new());
+    // a tokenizer can't return an error: the first one is kept until the operation that tokenized the text takes it
+    static TOKENIZER_ERROR: RefCell<Option<String>> = RefCell::new(None);
+}
+
+// A token returned by a JS tokenizer, with offsets in the JS string
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct JsToken {
+    // defaults to the text between the offsets
+    text: Option<String>,
+    offset_from: usize,
+    offset_to: usize,
+    // defaults to the position of the previous token plus one
+    position: Option<usize>,
+}
+
+// Registers a JS function `(text: string) => JsToken[]` as a tokenizer usable by the `text` fields of the `SegmentBuilder`
+// schemas and by the query parser. It must be registered before the segments using it are built, searched or merged.
+// The custom analyzers stored with the registered segments take precedence over a JS tokenizer of the same name.
+#[wasm_bindgen(js_name = "registerTokenizer")]
+pub fn register_tokenizer(name: &str, tokenizer: Function) -> Result<(), String> {
+    if is_builtin_tokenizer(name) {
+        return Err(WasmInterfaceError::TokenizerNameTaken(name.to_string()).to_string());
+    }
+    JS_TOKENIZERS.with(|tokenizers| tokenizers.borrow_mut().insert(name.to_string(), tokenizer));
+    Ok(())
+}
+
+pub fn registered_js_tokenizers() -> Vec<String> {
+    JS_TOKENIZERS.with(|tokenizers| tokenizers.borrow().keys().cloned().collect())
+}
+
+pub fn is_js_tokenizer(name: &str) -> bool {
+    JS_TOKENIZERS.with(|tokenizers| tokenizers.borrow().contains_key(name))
+}
+
+// The error of the first JS tokenizer that failed since the last call
+pub fn take_tokenizer_error() -> Option<String> {
+    TOKENIZER_ERROR.with(|error| error.borrow_mut().take())
+}
+
+#[derive(Clone)]
+pub struct JsTokenizer {
+    pub name: String,
+}
+
+impl JsTokenizer {
+    pub fn tokenize(&self, text: &str) -> Result<Vec<Token>, String> {
+        self.call(text).map_err(|err| WasmInterfaceError::TokenizerFailed(self.name.clone(), err).to_string())
+    }
+
+    fn call(&self, text: &str) -> Result<Vec<Token>, String> {
+        // the registry must not be borrowed during the call, the function may register a tokenizer
+        let function = JS_TOKENIZERS.with(|tokenizers| tokenizers.borrow().get(&self.name).cloned())
+                                    .ok_or_else(|| "it is not registered".to_string())?;
+        let js_tokens = function.call1(&JsValue::NULL, &JsValue::from_str(text)).map_err(|err| format!("{:?}", err))?;
+        let js_tokens: Vec<JsToken> = serde_wasm_bindgen::from_value(js_tokens).map_err(|err| err.to_string())?;
+        to_tokens(text, js_tokens)
+    }
+}
+
+// Byte offset in the UTF-8 text of a UTF-16 index, None if it is out of the text or in the middle of a character
+fn to_byte_offset(text: &str, utf16_index: usize) -> Option<usize> {
+    let mut utf16_offset = 0;
+    for (byte_offset, c) in text.char_indices() {
+        if utf16_offset == utf16_index {
+            return Some(byte_offset);
+        }
+        utf16_offset += c.len_utf16();
+    }
+    (utf16_offset == utf16_index).then(|| text.len())
+}
+
+fn to_tokens(text: &str, js_tokens: Vec<JsToken>) -> Result<Vec<Token>, String> {
+    let mut tokens: Vec<Token> = Vec::with_capacity(js_tokens.len());
+    for js_token in js_tokens {
+        let offsets = to_byte_offset(text, js_token.offset_from).zip(to_byte_offset(text, js_token.offset_to));
+        let (offset_from, offset_to) = offsets.filter(|(from, to)| from <= to)
+                                              .ok_or_else(|| format!("The offsets {}..{} are not valid in `{}`", js_token.offset_from, js_token.offset_to, text))?;
+        let token_text = &text[offset_from..offset_to];
+        let position = js_token.position.unwrap_or_else(|| tokens.last().map_or(0, |token| token.position + 1));
+        tokens.push(Token {
+            offset_from,
+            offset_to,
+            position,
+            text: js_token.text.unwrap_or_else(|| token_text.to_string()),
+            position_length: 1,
+        });
+    }
+    Ok(tokens)
+}
+
+impl Tokenizer for JsTokenizer {
+    fn token_stream<'a>(&self, text: &'a str) -> BoxTokenStream<'a> {
+        // the text is left without tokens, the error is returned by the search or the indexing that tokenized it
+        let tokens = self.tokenize(text).unwrap_or_else(|err| {
+            error!("{}", err);
+            TOKENIZER_ERROR.with(|error| error.borrow_mut().get_or_insert(err));
+            Vec::new()
+        });
+        BoxTokenStream::from(VecTokenStream::from(tokens))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::tokenizer::{TokenStream, Tokenizer};
+
+    use super::{take_tokenizer_error, to_tokens, JsToken, JsTokenizer};
+
+    #[test]
+    fn js_tokens() {
+        let js_tokens: Vec<JsToken> = serde_json::from_str(r#"[
+            {"offsetFrom": 0, "offsetTo": 4},
+            {"text": "example.com", "offsetFrom": 5, "offsetTo": 16},
+            {"text": "example", "offsetFrom": 5, "offsetTo": 12, "position": 1}
+        ]"#).unwrap();
+        let tokens: Vec<(String, usize)> = to_tokens("jane@example.com", js_tokens).unwrap()
+            .into_iter()
+            .map(|token| (token.text, token.position))
+            .collect();
+        assert_eq!(vec![("jane".to_string(), 0), ("example.com".to_string(), 1), ("example".to_string(), 1)], tokens);
+
+        let js_tokens: Vec<JsToken> = serde_json::from_str(r#"[{"offsetFrom": 3, "offsetTo": 8}]"#).unwrap();
+        let tokens = to_tokens("👋 #café", js_tokens).unwrap();
+        assert_eq!(("#café", 5, 11), (tokens[0].text.as_str(), tokens[0].offset_from, tokens[0].offset_to));
+
+        let js_tokens: Vec<JsToken> = serde_json::from_str(r#"[{"offsetFrom": 0, "offsetTo": 40}]"#).unwrap();
+        assert!(to_tokens("jane@example.com", js_tokens).is_err());
+        let js_tokens: Vec<JsToken> = serde_json::from_str(r#"[{"offsetFrom": 1, "offsetTo": 2}]"#).unwrap();
+        assert!(to_tokens("👋", js_tokens).is_err());
+    }
+
+    #[test]
+    fn tokenizer_errors() {
+        let tokenizer = JsTokenizer { name: "unregistered".to_string() };
+        assert!(tokenizer.tokenize("jane@example.com").is_err());
+
+        let mut token_count = 0;
+        tokenizer.token_stream("jane@example.com").process(&mut |_token| token_count += 1);
+        assert_eq!(0, token_count);
+        assert!(take_tokenizer_error().unwrap().contains("unregistered"));
+        assert_eq!(None, take_tokenizer_error());
+    }
+}
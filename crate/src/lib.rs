@@ -3,6 +3,16 @@ mod index;
 mod utils;
 mod new_api;
 mod errors;
+mod field_options;
+mod cursor;
+mod highlight;
+mod query_dsl;
+mod query_rewrite;
+mod query_errors;
+mod doc_query;
+mod tokenizers;
+mod cjk_tokenizer;
+mod js_tokenizer;
 
 pub use index::{Schema, Index, Document};
 use log::Level;
@@ -4,26 +4,35 @@ use serde::{Serialize, Deserialize};
 use wasm_bindgen::prelude::*;
 
 use serde_wasm_bindgen::Serializer;
+use time::{Date, OffsetDateTime, format_description::{self, well_known::Rfc3339}};
 
 use crate::errors::WasmInterfaceError;
+use crate::cursor::{Cursor, SearchAfter};
+use crate::highlight::{Highlight, HighlightOption, Highlighter};
+use crate::query_dsl::{SearchQuery, get_text_field};
+use crate::doc_query::DocQuery;
+use crate::js_tokenizer::{is_js_tokenizer, take_tokenizer_error};
+use crate::query_errors::{QueryError, QueryText, SearchError, parse_lenient};
+use crate::query_rewrite::{rewrite_leaves, leaf_field, extract_fuzzy_words, Synonyms, SynonymExpander, MAX_FUZZY_DISTANCE};
+use crate::tokenizers::{Analyzers, analyze, merge_analyzers, register_tokenizers, write_analyzers};
+use crate::field_options::{FieldFlags, date_options, facet_options, numeric_options, text_options};
 
 use tantivy::{
     schema::{
         Schema as TantivySchema,
         Document as TantivyDocument,
-        FAST,
-        INDEXED,
-        STRING,
-        STORED,
-        TEXT,
-        TextOptions, Field, NamedFieldDocument,
+        Field, NamedFieldDocument, FieldType, Value, Facet,
     },
-    DocAddress,
+    DateTime,
+    DocAddress, DocId, Score, Searcher, SegmentOrdinal,
     Index as TantivyIndex,
-    collector::TopDocs,
-    query::QueryParser,
+    collector::{TopDocs, Count, FacetCollector, MultiCollector, FruitHandle},
+    aggregation::{AggregationCollector, agg_req::Aggregations, agg_result::AggregationResults},
+    fastfield::FastValue,
+    query::{Query, QueryParser, BooleanQuery, Occur, TermQuery, FuzzyTermQuery, BoostQuery, MoreLikeThisQuery, Explanation},
     ReloadPolicy, IndexWriter as TantivyIndexWriter, Directory, Term,
-    SegmentWriter,
+    SegmentWriter, SegmentReader, DocSet, TERMINATED,
+    tokenizer::TokenStream,
     Segment as TantivySegment,
 };
 
@@ -32,15 +41,98 @@ use crate::hashmap_directory::{HashMapDirectory, SerializableHashMapDirectory};
 #[derive(Serialize, Deserialize, Default)]
 // Note: we must keep an Option<bool> for each field because serde(default) doesn't work with serde_wasm_bindgen: https://github.com/cloudflare/serde-wasm-bindgen/issues/20
 struct FieldPRoperties {
+    #[serde(rename = "type")]
+    field_type: Option<FieldKind>,
     fast: Option<bool>,
     indexed: Option<bool>,
     string: Option<bool>,
     stored: Option<bool>,
     text: Option<bool>,
+    // tokenizer of a `text` field, `default` when not set
+    tokenizer: Option<String>,
+}
+
+// The kind of value stored in a field. Fields without a `type` are text fields, configured with the `string`/`text` flags.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum FieldKind {
+    U64,
+    I64,
+    F64,
+    Date,
+    Facet,
+}
+
+// A value of a document as sent from JS: numeric fields accept both JS numbers and numeric strings,
+// date fields accept ISO-8601 strings and milliseconds since the epoch, facet fields accept paths like `/inbox/work`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum FieldValue {
+    Text(String),
+    Number(f64),
+}
+
+impl From<String> for FieldValue {
+    fn from(text: String) -> Self {
+        FieldValue::Text(text)
+    }
+}
+
+impl From<f64> for FieldValue {
+    fn from(number: f64) -> Self {
+        FieldValue::Number(number)
+    }
+}
+
+// A field of a document can hold one value or an array of values
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+enum DocumentValue {
+    Single(FieldValue),
+    Multiple(Vec<FieldValue>),
+}
+
+impl DocumentValue {
+    fn into_values(self) -> Vec<FieldValue> {
+        match self {
+            DocumentValue::Single(value) => vec![value],
+            DocumentValue::Multiple(values) => values,
+        }
+    }
+}
+
+impl From<FieldValue> for DocumentValue {
+    fn from(value: FieldValue) -> Self {
+        DocumentValue::Single(value)
+    }
+}
+
+impl From<String> for DocumentValue {
+    fn from(text: String) -> Self {
+        DocumentValue::Single(text.into())
+    }
+}
+
+impl From<Vec<FieldValue>> for DocumentValue {
+    fn from(values: Vec<FieldValue>) -> Self {
+        DocumentValue::Multiple(values)
+    }
+}
+
+impl FieldPRoperties {
+    fn flags(&self) -> FieldFlags {
+        FieldFlags {
+            fast: self.fast,
+            indexed: self.indexed,
+            string: self.string,
+            stored: self.stored,
+            text: self.text,
+        }
+    }
 }
 
 type Schema = HashMap<String, FieldPRoperties>;
-type Document = HashMap<String, String>;
+type Document = HashMap<String, DocumentValue>;
 
 #[wasm_bindgen]
 pub struct SegmentBuilder {
@@ -56,39 +148,60 @@ pub struct SegmentBuilder {
 #[wasm_bindgen]
 impl SegmentBuilder {
     #[wasm_bindgen(constructor)]
-    pub fn new(js_schema: JsValue, memory_arena_num_bytes: usize) -> Result<SegmentBuilder, String>{
+    // `js_analyzers` declares the custom analyzers that can be used as the `tokenizer` of text fields
+    pub fn new(js_schema: JsValue, memory_arena_num_bytes: usize, js_analyzers: JsValue) -> Result<SegmentBuilder, String>{
         let schema: Schema = serde_wasm_bindgen::from_value(js_schema).map_err(|err| err.to_string())?;
-        SegmentBuilder::new_inner(&schema, memory_arena_num_bytes)
+        let analyzers: Option<Analyzers> = serde_wasm_bindgen::from_value(js_analyzers).map_err(|err| err.to_string())?;
+        SegmentBuilder::with_analyzers_inner(&schema, &analyzers.unwrap_or_default(), memory_arena_num_bytes)
     }
 
     fn new_inner(schema: &Schema, memory_arena_num_bytes: usize) -> Result<SegmentBuilder, String>{
+        SegmentBuilder::with_analyzers_inner(schema, &Analyzers::new(), memory_arena_num_bytes)
+    }
+
+    fn with_analyzers_inner(schema: &Schema, analyzers: &Analyzers, memory_arena_num_bytes: usize) -> Result<SegmentBuilder, String>{
         let mut schema_builder = TantivySchema::builder();
 
         for (field_name, option) in schema.iter(){
-
-            // TODO implement the field options in a way that makes sense
-            let mut field_option = TextOptions::default();
-            // if option.fast.unwrap_or_default() {
-                // field_option = field_option | FAST;
-            // }
-            // if option.indexed.unwrap_or_default() {
-                // field_option = field_option | INDEXED;
-            // }
-            if option.string.unwrap_or_default() {
-                field_option = field_option | STRING;
+            let flags = option.flags();
+            match option.field_type {
+                Some(FieldKind::U64) => {
+                    schema_builder.add_u64_field(field_name, numeric_options(field_name, flags).map_err(|err| err.to_string())?);
+                },
+                Some(FieldKind::I64) => {
+                    schema_builder.add_i64_field(field_name, numeric_options(field_name, flags).map_err(|err| err.to_string())?);
+                },
+                Some(FieldKind::F64) => {
+                    schema_builder.add_f64_field(field_name, numeric_options(field_name, flags).map_err(|err| err.to_string())?);
+                },
+                Some(FieldKind::Date) => {
+                    schema_builder.add_date_field(field_name, date_options(field_name, flags).map_err(|err| err.to_string())?);
+                },
+                Some(FieldKind::Facet) => {
+                    schema_builder.add_facet_field(field_name, facet_options(field_name, flags).map_err(|err| err.to_string())?);
+                },
+                None => {
+                    schema_builder.add_text_field(field_name, text_options(field_name, flags, option.tokenizer.as_deref()).map_err(|err| err.to_string())?);
+                },
             }
-            if option.stored.unwrap_or_default() {
-                field_option = field_option | STORED;
-            }
-            if option.text.unwrap_or_default() {
-                field_option = field_option | TEXT;
-            }
-
-            schema_builder.add_text_field(field_name, field_option);
         }
         let tantivy_schema = schema_builder.build();
         let directory= HashMapDirectory::new();
+        if let Some(name) = analyzers.keys().find(|name| is_js_tokenizer(name)) {
+            return Err(WasmInterfaceError::TokenizerNameTaken(name.to_owned()).to_string());
+        }
+        if !analyzers.is_empty() {
+            write_analyzers(&directory, analyzers)?;
+        }
         let tantivy_index = TantivyIndex::builder().schema(tantivy_schema).open_or_create(directory.clone()).map_err(|err| err.to_string())?;
+        register_tokenizers(&tantivy_index)?;
+        for (field_name, option) in schema.iter(){
+            if let Some(tokenizer) = &option.tokenizer {
+                if tantivy_index.tokenizers().get(tokenizer).is_none() {
+                    return Err(WasmInterfaceError::UnknownTokenizer(field_name.to_owned(), tokenizer.to_owned()).to_string());
+                }
+            }
+        }
         let writer = tantivy_index.writer(memory_arena_num_bytes).map_err(|err| err.to_string())?;
 
         let (segment_writer, segment) = writer.get_segment_writer_and_segment().map_err(|err| err.to_string())?;
@@ -108,16 +221,45 @@ impl SegmentBuilder {
         self.add_document_inner(doc)
     }
 
-    fn add_document_inner(&mut self, doc: Document) -> Result<(), String>{
-
+    fn add_document_inner<V: Into<DocumentValue>>(&mut self, doc: HashMap<String, V>) -> Result<(), String>{
+        let schema = self.writer.index().schema();
         let mut tantivy_doc = TantivyDocument::default();
         for (field_name, data) in doc {
-            let field = self.writer.index().schema().get_field(&field_name).ok_or_else(||{WasmInterfaceError::InvalidField(field_name).to_string()})?;
-            tantivy_doc.add_text(field, data);
+            let field = schema.get_field(&field_name).ok_or_else(||{WasmInterfaceError::InvalidField(field_name.clone()).to_string()})?;
+            let field_entry = schema.get_field_entry(field);
+            let values = data.into().into_values();
+            // fast fields are single-valued, a column holds exactly one value per document
+            if values.len() > 1 && field_entry.is_fast() {
+                return Err(WasmInterfaceError::MultipleValuesInFastField(field_name).to_string());
+            }
+            for value in values {
+                match (field_entry.field_type(), value) {
+                    (FieldType::Str(_), FieldValue::Text(text)) => tantivy_doc.add_text(field, text),
+                    (FieldType::Str(_), FieldValue::Number(number)) => tantivy_doc.add_text(field, number.to_string()),
+                    (FieldType::U64(_), value) => tantivy_doc.add_u64(field, value.as_u64(&field_name)?),
+                    (FieldType::I64(_), value) => tantivy_doc.add_i64(field, value.as_i64(&field_name)?),
+                    (FieldType::F64(_), value) => tantivy_doc.add_f64(field, value.as_f64(&field_name)?),
+                    (FieldType::Date(_), value) => tantivy_doc.add_date(field, value.as_date(&field_name)?),
+                    (FieldType::Facet(_), value) => tantivy_doc.add_facet(field, value.as_facet(&field_name)?),
+                    _ => return Err(WasmInterfaceError::UnsupportedFieldType(field_name).to_string()),
+                }
+            }
         }
 
+        take_tokenizer_error();
         self.writer.add_document_to_segment_writer(&mut self.segment_writer, tantivy_doc).map_err(|err| err.to_string())?;
-        Ok(())
+        // the text a JS tokenizer failed on is left without terms, the rest of the document is indexed
+        match take_tokenizer_error() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    // -> Vec<AnalyzedToken>
+    // Tokens produced by the tokenizer of a text field, to debug what gets indexed
+    pub fn analyze(&self, field: &str, text: &str) -> Result<JsValue, String>{
+        let tokens = analyze(self.writer.index(), field, text)?;
+        Ok(tokens.serialize(&Serializer::new()).map_err(|err| err.to_string())?)
     }
 
     #[wasm_bindgen(js_name = "removeDocuments")]
@@ -145,6 +287,72 @@ impl SegmentBuilder {
     }
 }
 
+impl FieldValue {
+    pub fn as_f64(&self, field_name: &str) -> Result<f64, String> {
+        match self {
+            FieldValue::Number(number) => Ok(*number),
+            FieldValue::Text(text) => text.trim().parse().map_err(|_err| WasmInterfaceError::InvalidFieldValue(field_name.to_string(), text.clone()).to_string()),
+        }
+    }
+
+    pub fn as_i64(&self, field_name: &str) -> Result<i64, String> {
+        let number = self.as_f64(field_name)?;
+        // JS numbers are f64, only accept the ones holding an integer that fits in the target type
+        if number.fract() != 0.0 || number < i64::MIN as f64 || number >= i64::MAX as f64 {
+            return Err(WasmInterfaceError::InvalidFieldValue(field_name.to_string(), number.to_string()).to_string());
+        }
+        Ok(number as i64)
+    }
+
+    pub fn as_u64(&self, field_name: &str) -> Result<u64, String> {
+        let number = self.as_f64(field_name)?;
+        if number.fract() != 0.0 || number < 0.0 || number >= u64::MAX as f64 {
+            return Err(WasmInterfaceError::InvalidFieldValue(field_name.to_string(), number.to_string()).to_string());
+        }
+        Ok(number as u64)
+    }
+
+    pub fn as_date(&self, field_name: &str) -> Result<DateTime, String> {
+        match self {
+            FieldValue::Number(_) => Ok(DateTime::from_timestamp_millis(self.as_i64(field_name)?)),
+            FieldValue::Text(text) => {
+                let invalid = |_err| WasmInterfaceError::InvalidFieldValue(field_name.to_string(), text.clone()).to_string();
+                let date = match OffsetDateTime::parse(text, &Rfc3339) {
+                    Ok(date) => date,
+                    // a date without a time is midnight UTC, like `new Date("2022-01-01")` in JS
+                    Err(_err) => {
+                        let format = format_description::parse("[year]-[month]-[day]").map_err(|err| err.to_string())?;
+                        Date::parse(text, &format).map_err(invalid)?.midnight().assume_utc()
+                    },
+                };
+                Ok(DateTime::from_utc(date))
+            },
+        }
+    }
+
+    pub fn as_facet(&self, field_name: &str) -> Result<Facet, String> {
+        match self {
+            FieldValue::Text(path) => Facet::from_text(path).map_err(|_err| WasmInterfaceError::InvalidFieldValue(field_name.to_string(), path.clone()).to_string()),
+            FieldValue::Number(number) => Err(WasmInterfaceError::InvalidFieldValue(field_name.to_string(), number.to_string()).to_string()),
+        }
+    }
+}
+
+// Tokenizers can't fail while indexing: a JS tokenizer is called first, so that its failure rejects the document
+// Same as `TantivySchema::to_named_doc`, but dates are converted to ISO-8601 strings for JS
+fn to_named_doc(schema: &TantivySchema, doc: &TantivyDocument) -> Result<NamedFieldDocument, String> {
+    let NamedFieldDocument(mut field_map) = schema.to_named_doc(doc);
+    for values in field_map.values_mut() {
+        for value in values.iter_mut() {
+            if let Value::Date(date) = value {
+                let iso_date = date.into_utc().format(&Rfc3339).map_err(|err| err.to_string())?;
+                *value = Value::Str(iso_date);
+            }
+        }
+    }
+    Ok(NamedFieldDocument(field_map))
+}
+
 #[wasm_bindgen]
 pub struct Segment{
     directory: HashMapDirectory,
@@ -176,6 +384,10 @@ pub fn merge_segment(segments: Vec<Segment>) -> Segment{
 #[wasm_bindgen]
 pub struct SearchIndex {
     directory: Option<HashMapDirectory>,
+    // expanded in the query strings, the indexed segments are unchanged
+    synonyms: Synonyms,
+    // the synonyms tokenized by the tokenizers of the registered segments
+    synonym_expander: SynonymExpander,
 }
 
 #[wasm_bindgen]
@@ -185,6 +397,8 @@ impl SearchIndex {
     pub fn new() -> SearchIndex {
         SearchIndex{
             directory: None,
+            synonyms: Synonyms::new(),
+            synonym_expander: SynonymExpander::empty(),
         }
     }
 
@@ -193,6 +407,7 @@ impl SearchIndex {
     pub fn register_segment(&mut self, segment: Segment)-> Result<(), String>{
 
         if let Some(ref mut directory) = self.directory {
+            merge_analyzers(&*directory, &segment.directory)?;
             let this_index = TantivyIndex::open(directory.clone()).map_err(|err| err.to_string())?;
             let index_to_add = TantivyIndex::open(segment.directory.clone()).map_err(|err| err.to_string())?;
 
@@ -212,6 +427,34 @@ impl SearchIndex {
         }else{
             self.directory = Some(segment.directory);
         }
+        // the analyzers of the segment may change how the synonyms are tokenized
+        self.update_synonym_expander()
+    }
+
+    // Replaces the synonym dictionary: `{word: [alternatives]}`, words and alternatives can be phrases
+    #[wasm_bindgen(js_name = "setSynonyms")]
+    pub fn set_synonyms(&mut self, js_synonyms: JsValue)-> Result<(), String>{
+        let synonyms = serde_wasm_bindgen::from_value(js_synonyms).map_err(|err| err.to_string())?;
+        self.set_synonyms_inner(synonyms)
+    }
+    fn set_synonyms_inner(&mut self, synonyms: Synonyms)-> Result<(), String>{
+        self.synonyms = synonyms;
+        self.update_synonym_expander()
+    }
+
+    fn update_synonym_expander(&mut self)-> Result<(), String>{
+        self.synonym_expander = match self.directory {
+            Some(_) if !self.synonyms.is_empty() => {
+                let (index, _searcher) = self.searcher()?;
+                take_tokenizer_error();
+                let synonym_expander = SynonymExpander::new(&index, &self.synonyms)?;
+                if let Some(err) = take_tokenizer_error() {
+                    return Err(err);
+                }
+                synonym_expander
+            },
+            _ => SynonymExpander::empty(),
+        };
         Ok(())
     }
 
@@ -237,46 +480,235 @@ impl SearchIndex {
     }
 
     // -> SearchResult
-    pub fn search(&self, query: &str, js_option: JsValue)-> Result<JsValue, String>{
+    // the query is either a string in the QueryParser syntax or a structured query object.
+    // An invalid query string throws a `QueryError` object, the other errors are thrown as strings.
+    pub fn search(&self, js_query: JsValue, js_option: JsValue)-> Result<JsValue, JsValue>{
+        let query = SearchQuery::from_js(js_query)?;
         let option: SearchOption = serde_wasm_bindgen::from_value(js_option).map_err(|err| err.to_string())?;
 
         let results = self.search_inner(query, option)?;
         let serializer = Serializer::new().serialize_maps_as_objects(true);
         Ok(results.serialize(&serializer).map_err(|err| err.to_string())?)
     }
-    fn search_inner(&self, query: &str, option: SearchOption)-> Result<SearchResult, String>{
-        if let Some(ref directory) = self.directory {
-            let index = TantivyIndex::open(directory.clone()).map_err(|err| err.to_string())?;
-            let reader = index
-                .reader_builder()
-                .reload_policy(ReloadPolicy::Manual)
-                .try_into().map_err(|err| err.to_string())?;
-
-            let searcher = reader.searcher();
-            let fields_res = option.fields.iter().map(|field_name|{
-                let field = index.schema()
-                                 .get_field(&field_name)
-                                 .ok_or_else(||{
-                    WasmInterfaceError::InvalidField(field_name.to_owned()).to_string()
-                })?;
-                Ok(field)
-            }).collect::<Result<Vec<Field>, String>>();
-            let fields = fields_res?;
-            let query_parser = QueryParser::for_index(&index, fields);
-            let query = query_parser.parse_query(query).map_err(|err| err.to_string())?;
-            let top_docs = searcher.search(&query, &TopDocs::with_limit(option.limit))
-                                   .map_err(|err| err.to_string())?;
-
-            let mut results = Vec::new();
-            for (_score, doc_address) in top_docs {
-                let retrieved_doc = searcher.doc(doc_address)
-                                            .map_err(|err| err.to_string())?;
-                results.push(index.schema().to_named_doc(&retrieved_doc));
+    fn search_inner(&self, query: impl Into<SearchQuery>, option: SearchOption)-> Result<SearchResult, SearchError>{
+        let (index, searcher) = self.searcher()?;
+        let (query, errors) = parse_query(&index, &query.into(), &option, &self.synonym_expander)?;
+        let mut results = collect_hits(&index, &searcher, query.as_ref(), &option)?;
+        if option.lenient.unwrap_or_default() {
+            results.errors = Some(errors);
+        }
+        Ok(results)
+    }
+
+    // Number of documents matching the query, without retrieving them
+    pub fn count(&self, js_query: JsValue, js_option: JsValue)-> Result<usize, JsValue>{
+        let query = SearchQuery::from_js(js_query)?;
+        let option: QueryOption = serde_wasm_bindgen::from_value(js_option).map_err(|err| err.to_string())?;
+        Ok(self.count_inner(query, option)?)
+    }
+
+    fn count_inner(&self, query: impl Into<SearchQuery>, option: QueryOption)-> Result<usize, SearchError>{
+        let (index, searcher) = self.searcher()?;
+        let (query, _errors) = parse_query(&index, &query.into(), &option.into(), &self.synonym_expander)?;
+        Ok(searcher.search(&query, &Count).map_err(|err| err.to_string())?)
+    }
+
+    // Whether at least one document matches the query, stops at the first match
+    pub fn exists(&self, js_query: JsValue, js_option: JsValue)-> Result<bool, JsValue>{
+        let query = SearchQuery::from_js(js_query)?;
+        let option: QueryOption = serde_wasm_bindgen::from_value(js_option).map_err(|err| err.to_string())?;
+        Ok(self.exists_inner(query, option)?)
+    }
+
+    fn exists_inner(&self, query: impl Into<SearchQuery>, option: QueryOption)-> Result<bool, SearchError>{
+        let (index, searcher) = self.searcher()?;
+        let (query, _errors) = parse_query(&index, &query.into(), &option.into(), &self.synonym_expander)?;
+        let weight = query.weight(&searcher, false).map_err(|err| err.to_string())?;
+        for segment_reader in searcher.segment_readers() {
+            let mut scorer = weight.scorer(segment_reader, 1.0).map_err(|err| err.to_string())?;
+            let mut doc = scorer.doc();
+            while doc != TERMINATED {
+                if segment_reader.alive_bitset().map_or(true, |alive_bitset| alive_bitset.is_alive(doc)) {
+                    return Ok(true);
+                }
+                doc = scorer.advance();
             }
-            Ok(results)
-        }else{
-            Err(WasmInterfaceError::EmptyDirectory.to_string())
         }
+        Ok(false)
+    }
+
+    // -> Vec<Suggestion>
+    // Most frequent terms of a text field starting with the last token of `prefix`, for autocomplete
+    pub fn suggest(&self, field: &str, prefix: &str, limit: usize)-> Result<JsValue, String>{
+        let suggestions = self.suggest_inner(field, prefix, limit)?;
+        Ok(suggestions.serialize(&Serializer::new()).map_err(|err| err.to_string())?)
+    }
+
+    fn suggest_inner(&self, field_name: &str, prefix: &str, limit: usize)-> Result<Vec<Suggestion>, String>{
+        let (index, searcher) = self.searcher()?;
+        let field = get_text_field(&index, field_name)?;
+        // the prefix is normalized like the indexed terms, e.g. lowercased
+        let tokenizer = index.tokenizer_for_field(field).map_err(|err| err.to_string())?;
+        let mut prefix_token = String::new();
+        tokenizer.token_stream(prefix).process(&mut |token|{
+            prefix_token = token.text.clone();
+        });
+
+        // the document frequencies are summed over the segments, they include the deleted documents
+        let mut doc_freqs: HashMap<String, u32> = HashMap::new();
+        for segment_reader in searcher.segment_readers() {
+            let inverted_index = segment_reader.inverted_index(field).map_err(|err| err.to_string())?;
+            let mut terms = inverted_index.terms()
+                                          .range()
+                                          .ge(prefix_token.as_bytes())
+                                          .into_stream()
+                                          .map_err(|err| err.to_string())?;
+            while terms.advance() {
+                if !terms.key().starts_with(prefix_token.as_bytes()) {
+                    break;
+                }
+                if let Ok(term) = std::str::from_utf8(terms.key()) {
+                    *doc_freqs.entry(term.to_string()).or_default() += terms.value().doc_freq;
+                }
+            }
+        }
+
+        let mut suggestions: Vec<Suggestion> = doc_freqs.into_iter()
+                                                        .map(|(term, doc_freq)| Suggestion{term, doc_freq})
+                                                        .collect();
+        suggestions.sort_by(|a, b| b.doc_freq.cmp(&a.doc_freq).then_with(|| a.term.cmp(&b.term)));
+        suggestions.truncate(limit);
+        Ok(suggestions)
+    }
+
+    // -> Vec<AnalyzedToken>
+    // Tokens produced by the tokenizer of a text field, to debug what gets searched
+    pub fn analyze(&self, field: &str, text: &str)-> Result<JsValue, String>{
+        let (index, _searcher) = self.searcher()?;
+        let tokens = analyze(&index, field, text)?;
+        Ok(tokens.serialize(&Serializer::new()).map_err(|err| err.to_string())?)
+    }
+
+    // -> Explanation
+    // How the score of a hit (its `{segmentId, docId}`) is computed for a query, with the search options of the query
+    pub fn explain(&self, js_query: JsValue, js_hit_ref: JsValue, js_option: JsValue)-> Result<JsValue, JsValue>{
+        let query = SearchQuery::from_js(js_query)?;
+        let hit_ref: HitRef = serde_wasm_bindgen::from_value(js_hit_ref).map_err(|err| err.to_string())?;
+        let option: QueryOption = serde_wasm_bindgen::from_value(js_option).map_err(|err| err.to_string())?;
+
+        let explanation = self.explain_inner(query, &hit_ref, option)?;
+        Ok(explanation.serialize(&Serializer::new()).map_err(|err| err.to_string())?)
+    }
+
+    fn explain_inner(&self, query: impl Into<SearchQuery>, hit_ref: &HitRef, option: QueryOption)-> Result<Explanation, SearchError>{
+        let (index, searcher) = self.searcher()?;
+        let (query, _errors) = parse_query(&index, &query.into(), &option.into(), &self.synonym_expander)?;
+        let doc_address = hit_ref.doc_address(&searcher)?;
+        Ok(query.explain(&searcher, doc_address).map_err(|err| err.to_string())?)
+    }
+
+    // -> SearchResult
+    // Documents similar to an indexed document (the `{segmentId, docId}` of a hit) or to field values, ranked by relevance.
+    // The terms are taken from the stored values of an indexed document, the document itself is not returned.
+    #[wasm_bindgen(js_name = "moreLikeThis")]
+    pub fn more_like_this(&self, js_source: JsValue, js_option: JsValue)-> Result<JsValue, String>{
+        let source: MoreLikeThisSource = serde_wasm_bindgen::from_value(js_source).map_err(|err| err.to_string())?;
+        let option: MoreLikeThisOption = serde_wasm_bindgen::from_value(js_option).map_err(|err| err.to_string())?;
+
+        let results = self.more_like_this_inner(source, option)?;
+        let serializer = Serializer::new().serialize_maps_as_objects(true);
+        Ok(results.serialize(&serializer).map_err(|err| err.to_string())?)
+    }
+
+    fn more_like_this_inner(&self, source: MoreLikeThisSource, option: MoreLikeThisOption)-> Result<SearchResult, String>{
+        let (index, searcher) = self.searcher()?;
+        let schema = index.schema();
+        let fields = match &option.fields {
+            Some(field_names) => field_names.iter().map(|field_name| get_text_field(&index, field_name)).collect::<Result<Vec<Field>, String>>()?,
+            None => schema.fields()
+                          .filter(|(_, field_entry)| matches!(field_entry.field_type(), FieldType::Str(_)) && field_entry.is_indexed())
+                          .map(|(field, _)| field)
+                          .collect(),
+        };
+
+        let mut doc_fields: Vec<(Field, Vec<Value>)> = fields.iter().map(|field| (*field, Vec::new())).collect();
+        let mut add_value = |field: Field, value: Value|{
+            if let Some((_, values)) = doc_fields.iter_mut().find(|(doc_field, _)| *doc_field == field) {
+                values.push(value);
+            }
+        };
+        let source_doc = match source {
+            MoreLikeThisSource::Hit(hit_ref) => {
+                let doc_address = hit_ref.doc_address(&searcher)?;
+                let doc = searcher.doc(doc_address).map_err(|err| err.to_string())?;
+                for field_value in doc.field_values() {
+                    add_value(field_value.field(), field_value.value().clone());
+                }
+                Some(DocQuery{
+                    segment_id: searcher.segment_reader(doc_address.segment_ord).segment_id(),
+                    doc_id: doc_address.doc_id,
+                })
+            },
+            MoreLikeThisSource::Fields(field_values) => {
+                for (field_name, data) in field_values {
+                    let field = get_text_field(&index, &field_name)?;
+                    for value in data.into_values() {
+                        match value {
+                            FieldValue::Text(text) => add_value(field, Value::Str(text)),
+                            FieldValue::Number(number) => add_value(field, Value::Str(number.to_string())),
+                        }
+                    }
+                }
+                None
+            },
+        };
+
+        let mut query_builder = MoreLikeThisQuery::builder();
+        if let Some(min_term_frequency) = option.min_term_frequency {
+            query_builder = query_builder.with_min_term_frequency(min_term_frequency);
+        }
+        if let Some(min_doc_frequency) = option.min_doc_frequency {
+            query_builder = query_builder.with_min_doc_frequency(min_doc_frequency);
+        }
+        if let Some(max_doc_frequency) = option.max_doc_frequency {
+            query_builder = query_builder.with_max_doc_frequency(max_doc_frequency);
+        }
+        if let Some(max_query_terms) = option.max_query_terms {
+            query_builder = query_builder.with_max_query_terms(max_query_terms);
+        }
+        if let Some(min_word_length) = option.min_word_length {
+            query_builder = query_builder.with_min_word_length(min_word_length);
+        }
+        if let Some(max_word_length) = option.max_word_length {
+            query_builder = query_builder.with_max_word_length(max_word_length);
+        }
+        let mut query: Box<dyn Query> = Box::new(query_builder.with_document_fields(doc_fields));
+        // the source document is excluded by the query, so that the pages don't depend on where it ranks
+        if let Some(source_doc) = source_doc {
+            let source_doc: Box<dyn Query> = Box::new(source_doc);
+            query = Box::new(BooleanQuery::new(vec![(Occur::Must, query), (Occur::MustNot, source_doc)]));
+        }
+
+        let search_option = SearchOption{
+            limit: option.limit.unwrap_or(DEFAULT_LIMIT),
+            offset: option.offset,
+            ..Default::default()
+        };
+        let mut results = collect_hits(&index, &searcher, query.as_ref(), &search_option)?;
+        // the pages are fetched with `offset`
+        results.cursor = None;
+        Ok(results)
+    }
+
+    fn searcher(&self)-> Result<(TantivyIndex, Searcher), String>{
+        let directory = self.directory.as_ref().ok_or_else(||{WasmInterfaceError::EmptyDirectory.to_string()})?;
+        let index = TantivyIndex::open(directory.clone()).map_err(|err| err.to_string())?;
+        register_tokenizers(&index)?;
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into().map_err(|err| err.to_string())?;
+        Ok((index, reader.searcher()))
     }
 
     #[wasm_bindgen(js_name = "directorySummary")]
@@ -289,12 +721,433 @@ impl SearchIndex {
 
 
 
-type SearchResult= Vec<NamedFieldDocument>;
+// Collects the hits of a query with the pagination, sorting, highlighting and facet options of a search
+fn collect_hits(index: &TantivyIndex, searcher: &Searcher, query: &dyn Query, option: &SearchOption)-> Result<SearchResult, String>{
+    let limit = option.limit;
+    let highlighter = option.highlight.as_ref()
+                                      .map(|highlight_option| Highlighter::new(searcher, query, highlight_option))
+                                      .transpose()?;
+    let mut collectors = MultiCollector::new();
+    let offset = option.offset.unwrap_or_default();
+    let top_docs_handle = match (&option.sort_by, &option.cursor) {
+        (Some(_), Some(_)) => {
+            return Err(WasmInterfaceError::IncompatibleOptions("sortBy".to_string(), "cursor".to_string()).to_string());
+        },
+        (Some(sort_by), None) => {
+            let field = sortable_field(searcher, &sort_by.field)?;
+            let order = sort_by.order.unwrap_or_default();
+            // ties are broken by relevance
+            let top_docs = TopDocs::with_limit(limit).and_offset(offset).tweak_score(move |segment_reader: &SegmentReader|{
+                let fast_field = segment_reader.fast_fields().u64_lenient(field).expect("The sort field is checked to be a fast field");
+                move |doc: DocId, score: Score| (order.sort_key(fast_field.get_val(doc)), score)
+            });
+            TopDocsHandle::Sorted(field, order, collectors.add_collector(top_docs))
+        },
+        (None, Some(token)) => {
+            let cursor = Cursor::decode(token).map_err(|err| err.to_string())?;
+            TopDocsHandle::Relevance(collectors.add_collector(SearchAfter::new(&cursor, searcher, limit, offset)))
+        },
+        (None, None) => TopDocsHandle::Relevance(collectors.add_collector(TopDocs::with_limit(limit).and_offset(offset))),
+    };
+    let mut facet_handles = Vec::new();
+    for (field_name, facet_paths) in option.facets.iter().flatten() {
+        let field = index.schema()
+                         .get_field(field_name)
+                         .ok_or_else(||{
+            WasmInterfaceError::InvalidField(field_name.to_owned()).to_string()
+        })?;
+        let facets = facet_paths.iter().map(|path|{
+            Facet::from_text(path).map_err(|_err| WasmInterfaceError::InvalidFieldValue(field_name.to_owned(), path.to_owned()).to_string())
+        }).collect::<Result<Vec<Facet>, String>>()?;
+
+        let mut facet_collector = FacetCollector::for_field(field);
+        for facet in facets.iter() {
+            facet_collector.add_facet(facet.clone());
+        }
+        facet_handles.push((field_name, facets, collectors.add_collector(facet_collector)));
+    }
+    // computed on all the matching documents, whatever the pagination
+    let aggregations_handle = option.aggregations.clone().map(|aggregations|{
+        collectors.add_collector(AggregationCollector::from_aggs(aggregations, None, index.schema()))
+    });
+
+    let mut fruits = searcher.search(query, &collectors)
+                           .map_err(|err| err.to_string())?;
+
+    let mut hits = Vec::new();
+    match top_docs_handle {
+        TopDocsHandle::Relevance(handle) => {
+            for (score, doc_address) in handle.extract(&mut fruits) {
+                hits.push(Hit::retrieve(searcher, score, doc_address, highlighter.as_ref())?);
+            }
+        },
+        TopDocsHandle::Sorted(field, order, handle) => {
+            let schema = index.schema();
+            for ((sort_key, score), doc_address) in handle.extract(&mut fruits) {
+                let mut hit = Hit::retrieve(searcher, score, doc_address, highlighter.as_ref())?;
+                hit.sort_value = Some(sort_value(schema.get_field_entry(field).field_type(), order.sort_key(sort_key))?);
+                hits.push(hit);
+            }
+        },
+    }
+
+    let facets = if option.facets.is_some() {
+        let mut facets = HashMap::new();
+        for (field_name, requested_facets, handle) in facet_handles {
+            let facet_counts = handle.extract(&mut fruits);
+            let counts: &mut HashMap<String, u64> = facets.entry(field_name.to_owned()).or_default();
+            for requested_facet in requested_facets {
+                for (facet, count) in facet_counts.get(requested_facet) {
+                    counts.insert(facet.to_string(), count);
+                }
+            }
+        }
+        Some(facets)
+    }else{
+        None
+    };
+
+    let aggregations = aggregations_handle.map(|handle| handle.extract(&mut fruits));
+
+    // a cursor only makes sense for a ranking by relevance
+    let cursor = hits.last().filter(|_| option.sort_by.is_none()).map(|hit|{
+        Cursor{
+            score: hit.score,
+            segment_id: hit.segment_id.clone(),
+            doc_id: hit.doc_id,
+        }.encode()
+    });
+
+    Ok(SearchResult{
+        hits,
+        facets,
+        aggregations,
+        cursor,
+        errors: None,
+    })
+}
+
+// Returns the errors of the query string skipped in `lenient` mode, they are returned as an error otherwise
+fn parse_query(index: &TantivyIndex, query: &SearchQuery, option: &SearchOption, synonym_expander: &SynonymExpander)-> Result<(Box<dyn Query>, Vec<QueryError>), SearchError>{
+    // a JS tokenizer failing on the query would make it silently match nothing
+    take_tokenizer_error();
+    let parsed_query = parse_query_inner(index, query, option, synonym_expander)?;
+    match take_tokenizer_error() {
+        Some(err) => Err(err.into()),
+        None => Ok(parsed_query),
+    }
+}
+
+fn parse_query_inner(index: &TantivyIndex, query: &SearchQuery, option: &SearchOption, synonym_expander: &SynonymExpander)-> Result<(Box<dyn Query>, Vec<QueryError>), SearchError>{
+    let query = match query {
+        SearchQuery::Text(query) => query,
+        SearchQuery::Dsl(query) => return Ok((query.to_query(index)?, Vec::new())),
+    };
+    let fields_res = option.fields.iter().map(|field_name|{
+        let field = index.schema()
+                         .get_field(&field_name)
+                         .ok_or_else(||{
+            WasmInterfaceError::InvalidField(field_name.to_owned()).to_string()
+        })?;
+        Ok(field)
+    }).collect::<Result<Vec<Field>, String>>();
+    let fields = fields_res?;
+    let mut boosts = HashMap::new();
+    for (field_name, boost) in option.boosts.iter().flatten() {
+        let field = index.schema().get_field(field_name).ok_or_else(||{WasmInterfaceError::InvalidField(field_name.to_owned()).to_string()})?;
+        boosts.insert(field, *boost);
+    }
+    let mut query_parser = QueryParser::for_index(index, fields.clone());
+    if option.conjunction.unwrap_or_default() {
+        query_parser.set_conjunction_by_default();
+    }
+    let original_query = query;
+    let (query, fuzzy_words) = extract_fuzzy_words(original_query);
+    let query_text = QueryText::new(original_query, &query, &fuzzy_words);
+    let rewrite = option.fuzzy.is_some() || !fuzzy_words.is_empty() || !synonym_expander.is_empty();
+    // the leaves to rewrite would be hidden in the `BoostQuery`s of the parser: the boosts are applied after the rewriting
+    if !rewrite {
+        for (field, boost) in boosts.iter() {
+            query_parser.set_field_boost(*field, *boost);
+        }
+    }
+    let (query, errors) = if option.lenient.unwrap_or_default() {
+        parse_lenient(&query_parser, &query_text)
+    } else {
+        let parsed_query = query_parser.parse_query(&query).map_err(|err| SearchError::Query(query_text.error(&err)))?;
+        (parsed_query, Vec::new())
+    };
+    if !rewrite {
+        return Ok((query, errors));
+    }
+
+    let distances = fuzzy_words.iter().map(|fuzzy_word| fuzzy_word.distance).chain(option.fuzzy.as_ref().and_then(|fuzzy| fuzzy.distance));
+    for distance in distances {
+        if distance > MAX_FUZZY_DISTANCE {
+            return Err(WasmInterfaceError::InvalidFuzzyDistance(distance).to_string().into());
+        }
+    }
+    let transpositions = option.fuzzy.as_ref().and_then(|fuzzy| fuzzy.transpositions).unwrap_or(true);
+    let prefix = option.fuzzy.as_ref().and_then(|fuzzy| fuzzy.prefix).unwrap_or_default();
+    let to_fuzzy_query = |leaf: &dyn Query| -> Option<Box<dyn Query>> {
+        let term = leaf.downcast_ref::<TermQuery>()?.term();
+        let text = term.as_str()?;
+        let tokenizer = index.tokenizer_for_field(term.field()).ok()?;
+        // a `word~N` in the query string takes precedence over the fuzzy option
+        let distance = match (fuzzy_words.iter().find(|fuzzy_word| fuzzy_word.matches(&tokenizer, text)), &option.fuzzy) {
+            (Some(fuzzy_word), _) => fuzzy_word.distance,
+            (None, Some(fuzzy)) if fields.contains(&term.field()) => fuzzy.distance.unwrap_or(1),
+            _ => return None,
+        };
+        let fuzzy_query = if prefix {
+            FuzzyTermQuery::new_prefix(term.clone(), distance, transpositions)
+        } else {
+            FuzzyTermQuery::new(term.clone(), distance, transpositions)
+        };
+        Some(Box::new(fuzzy_query))
+    };
+    let rewritten_query = rewrite_leaves(query.as_ref(), &mut |leaf: &dyn Query| -> Option<Box<dyn Query>> {
+        // the alternatives are matched exactly, only the words of the query are fuzzy
+        let query = to_fuzzy_query(leaf).unwrap_or_else(|| leaf.box_clone());
+        let query = synonym_expander.expand(leaf, query);
+        match leaf_field(leaf).and_then(|field| boosts.get(&field)) {
+            Some(boost) => Some(Box::new(BoostQuery::new(query, *boost))),
+            None => Some(query),
+        }
+    });
+    Ok((synonym_expander.expand_words(query.as_ref(), rewritten_query), errors))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Hit{
+    score: Score,
+    // the segment id and the doc id identify the document in the SearchIndex, the doc id alone is only unique in its segment
+    segment_id: String,
+    doc_id: DocId,
+    doc: NamedFieldDocument,
+    // value of the `sortBy` field when the hits are sorted by a field
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sort_value: Option<Value>,
+    // snippets of the `highlight` fields, by field
+    #[serde(skip_serializing_if = "Option::is_none")]
+    highlights: Option<HashMap<String, Highlight>>,
+}
+
+impl Hit {
+    fn retrieve(searcher: &Searcher, score: Score, doc_address: DocAddress, highlighter: Option<&Highlighter>) -> Result<Hit, String> {
+        let retrieved_doc = searcher.doc(doc_address)
+                                    .map_err(|err| err.to_string())?;
+        Ok(Hit{
+            score,
+            segment_id: searcher.segment_reader(doc_address.segment_ord).segment_id().uuid_string(),
+            doc_id: doc_address.doc_id,
+            doc: to_named_doc(&searcher.schema(), &retrieved_doc)?,
+            sort_value: None,
+            highlights: highlighter.map(|highlighter| highlighter.highlight(&retrieved_doc)),
+        })
+    }
+}
+
+// A term of a text field, for autocomplete
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct Suggestion{
+    term: String,
+    // number of documents containing the term
+    doc_freq: u32,
+}
+
+// A document of the SearchIndex, as identified in the hits. The other fields of a hit are ignored, so a hit can be passed as is
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct HitRef{
+    segment_id: String,
+    doc_id: DocId,
+}
+
+impl HitRef {
+    // segment ordinals depend on the registered segments, the segment is found by id
+    fn doc_address(&self, searcher: &Searcher) -> Result<DocAddress, String> {
+        let segment_ord = searcher.segment_readers()
+                                  .iter()
+                                  .position(|segment_reader| segment_reader.segment_id().uuid_string() == self.segment_id)
+                                  .filter(|segment_ord| self.doc_id < searcher.segment_reader(*segment_ord as SegmentOrdinal).max_doc())
+                                  .ok_or_else(||{
+            WasmInterfaceError::InvalidHitRef(self.segment_id.clone(), self.doc_id).to_string()
+        })?;
+        Ok(DocAddress::new(segment_ord as SegmentOrdinal, self.doc_id))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MoreLikeThisSource{
+    Hit(HitRef),
+    Fields(HashMap<String, DocumentValue>),
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct MoreLikeThisOption{
+    // text fields whose terms are compared, defaults to all the indexed text fields
+    fields: Option<Vec<String>>,
+    // number of hits to return, defaults to DEFAULT_LIMIT
+    limit: Option<usize>,
+    // number of hits to skip
+    offset: Option<usize>,
+    // terms appearing fewer times in the source are ignored, defaults to 2
+    min_term_frequency: Option<usize>,
+    // terms appearing in fewer documents are ignored, defaults to 5
+    min_doc_frequency: Option<u64>,
+    max_doc_frequency: Option<u64>,
+    // number of terms of the source with the best scores kept in the query, defaults to 25
+    max_query_terms: Option<usize>,
+    min_word_length: Option<usize>,
+    max_word_length: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct SearchResult{
+    hits: Vec<Hit>,
+    // document counts of the children of each requested facet, by field
+    #[serde(skip_serializing_if = "Option::is_none")]
+    facets: Option<HashMap<String, HashMap<String, u64>>>,
+    // results of the `aggregations` option, in the Elasticsearch format
+    #[serde(skip_serializing_if = "Option::is_none")]
+    aggregations: Option<AggregationResults>,
+    // token to pass as the `cursor` option to get the hits after the last one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cursor: Option<String>,
+    // errors of the query string skipped in `lenient` mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    errors: Option<Vec<QueryError>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl SortOrder {
+    // fast fields values are mapped to u64 in an order-preserving way, `TopDocs` keeps the greatest keys
+    fn sort_key(self, fast_value: u64) -> u64 {
+        match self {
+            SortOrder::Asc => u64::MAX - fast_value,
+            SortOrder::Desc => fast_value,
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize)]
+struct SortBy{
+    field: String,
+    order: Option<SortOrder>,
+}
+
+enum TopDocsHandle {
+    Relevance(FruitHandle<Vec<(Score, DocAddress)>>),
+    Sorted(Field, SortOrder, FruitHandle<Vec<((u64, Score), DocAddress)>>),
+}
+
+fn sortable_field(searcher: &Searcher, field_name: &str) -> Result<Field, String> {
+    let schema = searcher.schema();
+    let field = schema.get_field(field_name).ok_or_else(||{WasmInterfaceError::InvalidField(field_name.to_string()).to_string()})?;
+    let field_entry = schema.get_field_entry(field);
+    match field_entry.field_type() {
+        FieldType::U64(_) | FieldType::I64(_) | FieldType::F64(_) | FieldType::Date(_) if field_entry.is_fast() => {},
+        _ => return Err(WasmInterfaceError::NotSortable(field_name.to_string()).to_string()),
+    }
+    // the registered segments may have been built with different schemas, the field must be fast in all of them
+    for segment_reader in searcher.segment_readers() {
+        if segment_reader.fast_fields().u64_lenient(field).is_err() {
+            return Err(WasmInterfaceError::NotSortable(field_name.to_string()).to_string());
+        }
+    }
+    Ok(field)
+}
+
+// Converts back the fast field value of a hit to the type of the field
+fn sort_value(field_type: &FieldType, fast_value: u64) -> Result<Value, String> {
+    let value = match field_type {
+        FieldType::I64(_) => Value::I64(i64::from_u64(fast_value)),
+        FieldType::F64(_) => Value::F64(f64::from_u64(fast_value)),
+        FieldType::Date(_) => {
+            let iso_date = DateTime::from_u64(fast_value).into_utc().format(&Rfc3339).map_err(|err| err.to_string())?;
+            Value::Str(iso_date)
+        },
+        _ => Value::U64(fast_value),
+    };
+    Ok(value)
+}
+
+const DEFAULT_LIMIT: usize = 10;
+
+#[derive(Serialize, Deserialize, Default)]
+struct FuzzyOption{
+    // Levenshtein distance, defaults to 1
+    distance: Option<u8>,
+    // count a transposition as a single edit, defaults to true
+    transpositions: Option<bool>,
+    // match the terms starting with a word at the given distance, for search-as-you-type
+    prefix: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
 struct SearchOption{
+    // terms without a `field:` prefix are not searched when missing
+    #[serde(default)]
     fields: Vec<String>,
     limit: usize,
+    // number of hits to skip
+    offset: Option<usize>,
+    // resume the search after the last hit of a previous result, from its `cursor`
+    cursor: Option<String>,
+    // sort the hits by a fast field instead of relevance
+    sort_by: Option<SortBy>,
+    // snippets with the matched terms highlighted, for stored text fields
+    highlight: Option<HighlightOption>,
+    // match the terms of the query on `fields` with typos
+    fuzzy: Option<FuzzyOption>,
+    // facet paths (`/` for the root) whose children are counted, by field
+    facets: Option<HashMap<String, Vec<String>>>,
+    // Elasticsearch-compatible aggregations (terms, histogram, range, stats...) over fast fields, by name
+    aggregations: Option<Aggregations>,
+    // score multipliers of the matches of a query string, by field
+    boosts: Option<HashMap<String, Score>>,
+    // all the words of a query string must match instead of any
+    conjunction: Option<bool>,
+    // search with the parts of a query string that can be parsed instead of failing, the errors are in the result
+    lenient: Option<bool>,
+}
+
+// The options of `count`, `exists` and `explain`: the search options that change which documents match and their scores
+#[derive(Serialize, Deserialize, Default)]
+struct QueryOption{
+    // terms without a `field:` prefix are not searched when missing
+    #[serde(default)]
+    fields: Vec<String>,
+    fuzzy: Option<FuzzyOption>,
+    boosts: Option<HashMap<String, Score>>,
+    conjunction: Option<bool>,
+    lenient: Option<bool>,
+}
+
+impl From<QueryOption> for SearchOption {
+    fn from(option: QueryOption) -> Self {
+        SearchOption{
+            fields: option.fields,
+            fuzzy: option.fuzzy,
+            boosts: option.boosts,
+            conjunction: option.conjunction,
+            lenient: option.lenient,
+            ..Default::default()
+        }
+    }
 }
 
 #[wasm_bindgen]
@@ -333,6 +1186,7 @@ impl Merger{
                 let directory = self.search_index.directory.ok_or_else(||{WasmInterfaceError::EmptyDirectory.to_string()})?;
 
                 let tantivy_index = TantivyIndex::open(directory.clone()).map_err(|err| err.to_string())?;
+                register_tokenizers(&tantivy_index)?;
                 let mut writer = tantivy_index.writer(50_000_000).map_err(|err| err.to_string())?;
                 let searchable_doc_id = writer.index().searchable_segment_ids().map_err(|err| err.to_string())?;
                 writer.merge(&searchable_doc_id).map_err(|err| err.to_string())?;
@@ -349,10 +1203,18 @@ impl Merger{
 #[cfg(test)]
 mod test{
     use common_macros::hash_map;
+    use tantivy::schema::Value;
 
-    use crate::{SegmentBuilder, SearchIndex, new_api::Merger};
+    use crate::{SegmentBuilder, Segment, SearchIndex, new_api::Merger};
 
-    use super::{FieldPRoperties, SearchOption};
+    use crate::tokenizers::{Analyzers, AnalyzedToken, analyze};
+    use super::{FieldPRoperties, FieldKind, FieldValue, DocumentValue, SearchOption, QueryOption, SearchResult, SortBy, SortOrder, FuzzyOption, Suggestion, HitRef, MoreLikeThisSource, MoreLikeThisOption};
+    use crate::highlight::{HighlightOption, HighlightRange};
+    use crate::query_dsl::QueryDsl;
+    use crate::query_errors::{QueryErrorKind, SearchError};
+    use std::collections::HashMap;
+    use serde_json::json;
+    use tantivy::aggregation::agg_req::Aggregations;
 
     #[test]
     fn simple_search(){
@@ -390,10 +1252,10 @@ mod test{
         let mut search_index = SearchIndex::new();
         search_index.register_segment(segment).unwrap();
 
-        let results = search_index.search_inner("the", SearchOption{fields: vec!["title".to_string()], limit: 10}).unwrap();
-        assert_eq!(2, results.len());
-        let results = search_index.search_inner("the", SearchOption{fields: vec!["body".to_string()], limit: 10}).unwrap();
-        assert_eq!(3, results.len());
+        let results = search_index.search_inner("the", SearchOption{fields: vec!["title".to_string()], limit: 10, ..Default::default()}).unwrap();
+        assert_eq!(2, results.hits.len());
+        let results = search_index.search_inner("the", SearchOption{fields: vec!["body".to_string()], limit: 10, ..Default::default()}).unwrap();
+        assert_eq!(3, results.hits.len());
     }
 
     #[test]
@@ -459,10 +1321,10 @@ mod test{
         let mut search_index = SearchIndex::new();
         search_index.register_segment(merged_segment).unwrap();
 
-        let results = search_index.search_inner("the", SearchOption{fields: vec!["title".to_string()], limit: 10}).unwrap();
-        assert_eq!(2, results.len());
-        let results = search_index.search_inner("the", SearchOption{fields: vec!["body".to_string()], limit: 10}).unwrap();
-        assert_eq!(4, results.len());
+        let results = search_index.search_inner("the", SearchOption{fields: vec!["title".to_string()], limit: 10, ..Default::default()}).unwrap();
+        assert_eq!(2, results.hits.len());
+        let results = search_index.search_inner("the", SearchOption{fields: vec!["body".to_string()], limit: 10, ..Default::default()}).unwrap();
+        assert_eq!(4, results.hits.len());
     }
 
     #[test]
@@ -512,10 +1374,996 @@ mod test{
         let mut search_index = SearchIndex::new();
         search_index.register_segment(merged_segment).unwrap();
 
-        let results = search_index.search_inner("the", SearchOption{fields: vec!["title".to_string()], limit: 10}).unwrap();
-        assert_eq!(2, results.len());
-        let results = search_index.search_inner("the", SearchOption{fields: vec!["body".to_string()], limit: 10}).unwrap();
-        assert_eq!(2, results.len());
+        let results = search_index.search_inner("the", SearchOption{fields: vec!["title".to_string()], limit: 10, ..Default::default()}).unwrap();
+        assert_eq!(2, results.hits.len());
+        let results = search_index.search_inner("the", SearchOption{fields: vec!["body".to_string()], limit: 10, ..Default::default()}).unwrap();
+        assert_eq!(2, results.hits.len());
+    }
+
+    #[test]
+    fn numeric_fields(){
+        let schema = hash_map! {
+                "title".to_string() => FieldPRoperties{text: Some(true), stored: Some(true), ..Default::default()},
+                "price".to_string() => FieldPRoperties{field_type: Some(FieldKind::U64), stored: Some(true), ..Default::default()},
+                "balance".to_string() => FieldPRoperties{field_type: Some(FieldKind::I64), ..Default::default()},
+                "rating".to_string() => FieldPRoperties{field_type: Some(FieldKind::F64), ..Default::default()},
+            };
+
+        let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+
+        segment_builder.add_document_inner(hash_map! {
+          "title".to_string() => FieldValue::from("Lord Of The Rings".to_string()),
+          "price".to_string() => FieldValue::from(12.0),
+          "balance".to_string() => FieldValue::from(-3.0),
+          "rating".to_string() => FieldValue::from(4.5),
+        }).unwrap();
+        segment_builder.add_document_inner(hash_map! {
+          "title".to_string() => FieldValue::from("The Old Man and the Sea".to_string()),
+          "price".to_string() => FieldValue::from("25".to_string()),
+          "balance".to_string() => FieldValue::from(7.0),
+          "rating".to_string() => FieldValue::from(3.0),
+        }).unwrap();
+
+        assert!(segment_builder.add_document_inner(hash_map! {
+          "price".to_string() => FieldValue::from(-1.0),
+        }).is_err());
+        assert!(segment_builder.add_document_inner(hash_map! {
+          "balance".to_string() => FieldValue::from(1.5),
+        }).is_err());
+        // 2^63 and 2^64 are the first f64 values out of range, they would be saturated to i64::MAX / u64::MAX
+        assert!(segment_builder.add_document_inner(hash_map! {
+          "balance".to_string() => FieldValue::from(i64::MAX as f64),
+        }).is_err());
+        assert!(segment_builder.add_document_inner(hash_map! {
+          "price".to_string() => FieldValue::from(u64::MAX as f64),
+        }).is_err());
+
+        let segment = segment_builder.finalize().unwrap();
+
+        let mut search_index = SearchIndex::new();
+        search_index.register_segment(segment).unwrap();
+
+        let results = search_index.search_inner("price:[10 TO 20]", SearchOption{fields: vec!["title".to_string()], limit: 10, ..Default::default()}).unwrap();
+        assert_eq!(1, results.hits.len());
+        let results = search_index.search_inner("balance:[-5 TO 10] AND rating:[3.5 TO 5]", SearchOption{fields: vec!["title".to_string()], limit: 10, ..Default::default()}).unwrap();
+        assert_eq!(1, results.hits.len());
+        let results = search_index.search_inner("price:25", SearchOption{fields: vec!["title".to_string()], limit: 10, ..Default::default()}).unwrap();
+        assert_eq!(1, results.hits.len());
+    }
+
+    #[test]
+    fn invalid_field_options(){
+        let schema = hash_map! {
+                "body".to_string() => FieldPRoperties{text: Some(true), fast: Some(true), ..Default::default()},
+            };
+        assert!(SegmentBuilder::new_inner(&schema, 50_000_000).is_err());
+
+        let schema = hash_map! {
+                "id".to_string() => FieldPRoperties{string: Some(true), indexed: Some(false), ..Default::default()},
+            };
+        assert!(SegmentBuilder::new_inner(&schema, 50_000_000).is_err());
+
+        let schema = hash_map! {
+                "price".to_string() => FieldPRoperties{field_type: Some(FieldKind::U64), text: Some(true), ..Default::default()},
+            };
+        assert!(SegmentBuilder::new_inner(&schema, 50_000_000).is_err());
+
+        let schema = hash_map! {
+                "id".to_string() => FieldPRoperties{string: Some(true), fast: Some(true), ..Default::default()},
+                "price".to_string() => FieldPRoperties{field_type: Some(FieldKind::U64), fast: Some(true), indexed: Some(false), ..Default::default()},
+            };
+        assert!(SegmentBuilder::new_inner(&schema, 50_000_000).is_ok());
+    }
+
+    #[test]
+    fn date_fields(){
+        let schema = hash_map! {
+                "subject".to_string() => FieldPRoperties{text: Some(true), stored: Some(true), ..Default::default()},
+                "received".to_string() => FieldPRoperties{field_type: Some(FieldKind::Date), stored: Some(true), ..Default::default()},
+            };
+
+        let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+
+        segment_builder.add_document_inner(hash_map! {
+          "subject".to_string() => FieldValue::from("Happy new year".to_string()),
+          "received".to_string() => FieldValue::from("2022-01-01T10:00:00Z".to_string()),
+        }).unwrap();
+        segment_builder.add_document_inner(hash_map! {
+          "subject".to_string() => FieldValue::from("Merry christmas".to_string()),
+          // 2021-12-25T00:00:00Z
+          "received".to_string() => FieldValue::from(1640390400000.0),
+        }).unwrap();
+        segment_builder.add_document_inner(hash_map! {
+          "subject".to_string() => FieldValue::from("Happy birthday".to_string()),
+          "received".to_string() => FieldValue::from("2021-11-02".to_string()),
+        }).unwrap();
+        assert!(segment_builder.add_document_inner(hash_map! {
+          "received".to_string() => FieldValue::from("yesterday".to_string()),
+        }).is_err());
+
+        let segment = segment_builder.finalize().unwrap();
+
+        let mut search_index = SearchIndex::new();
+        search_index.register_segment(segment).unwrap();
+
+        let results = search_index.search_inner("received:[2022-01-01T00:00:00Z TO *]", SearchOption{fields: vec!["subject".to_string()], limit: 10, ..Default::default()}).unwrap();
+        assert_eq!(1, results.hits.len());
+        assert_eq!(vec![Value::Str("2022-01-01T10:00:00Z".to_string())], results.hits[0].doc.0["received"]);
+
+        let results = search_index.search_inner("received:[2021-12-01T00:00:00Z TO 2021-12-31T00:00:00Z]", SearchOption{fields: vec!["subject".to_string()], limit: 10, ..Default::default()}).unwrap();
+        assert_eq!(1, results.hits.len());
+        assert_eq!(vec![Value::Str("2021-12-25T00:00:00Z".to_string())], results.hits[0].doc.0["received"]);
+
+        let results = search_index.search_inner("received:[2021-11-02T00:00:00Z TO 2021-11-03T00:00:00Z}", SearchOption{fields: vec!["subject".to_string()], limit: 10, ..Default::default()}).unwrap();
+        assert_eq!(1, results.hits.len());
+        assert_eq!(vec![Value::Str("2021-11-02T00:00:00Z".to_string())], results.hits[0].doc.0["received"]);
+    }
+
+    #[test]
+    fn facet_counts(){
+        let schema = hash_map! {
+                "subject".to_string() => FieldPRoperties{text: Some(true), stored: Some(true), ..Default::default()},
+                "folder".to_string() => FieldPRoperties{field_type: Some(FieldKind::Facet), stored: Some(true), ..Default::default()},
+            };
+
+        let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+
+        segment_builder.add_document_inner(hash_map! {
+          "subject".to_string() => "Meeting notes".to_string(),
+          "folder".to_string() => "/inbox/work".to_string(),
+        }).unwrap();
+        segment_builder.add_document_inner(hash_map! {
+          "subject".to_string() => "Meeting with the bank".to_string(),
+          "folder".to_string() => "/inbox/personal".to_string(),
+        }).unwrap();
+        segment_builder.add_document_inner(hash_map! {
+          "subject".to_string() => "Meeting room booked".to_string(),
+          "folder".to_string() => "/inbox/work".to_string(),
+        }).unwrap();
+        segment_builder.add_document_inner(hash_map! {
+          "subject".to_string() => "Your invoice".to_string(),
+          "folder".to_string() => "/archive".to_string(),
+        }).unwrap();
+        assert!(segment_builder.add_document_inner(hash_map! {
+          "folder".to_string() => "inbox".to_string(),
+        }).is_err());
+
+        let segment = segment_builder.finalize().unwrap();
+
+        let mut search_index = SearchIndex::new();
+        search_index.register_segment(segment).unwrap();
+
+        let results = search_index.search_inner("meeting", SearchOption{
+            fields: vec!["subject".to_string()],
+            limit: 1,
+            facets: Some(hash_map!{"folder".to_string() => vec!["/".to_string(), "/inbox".to_string()]}),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(1, results.hits.len());
+        let folders: HashMap<String, u64> = hash_map!{
+            "/inbox".to_string() => 3,
+            "/inbox/work".to_string() => 2,
+            "/inbox/personal".to_string() => 1,
+        };
+        assert_eq!(Some(hash_map!{"folder".to_string() => folders}), results.facets);
+
+        let results = search_index.search_inner("folder:/inbox/work", SearchOption{fields: vec!["subject".to_string()], limit: 10, ..Default::default()}).unwrap();
+        assert_eq!(2, results.hits.len());
+    }
+
+    #[test]
+    fn multi_valued_fields(){
+        let schema = hash_map! {
+                "subject".to_string() => FieldPRoperties{text: Some(true), stored: Some(true), ..Default::default()},
+                "recipients".to_string() => FieldPRoperties{string: Some(true), stored: Some(true), ..Default::default()},
+                "sizes".to_string() => FieldPRoperties{field_type: Some(FieldKind::U64), stored: Some(true), ..Default::default()},
+                "priority".to_string() => FieldPRoperties{field_type: Some(FieldKind::U64), fast: Some(true), ..Default::default()},
+            };
+
+        let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+
+        segment_builder.add_document_inner(hash_map! {
+          "subject".to_string() => DocumentValue::from("Lunch".to_string()),
+          "recipients".to_string() => DocumentValue::from(vec![FieldValue::from("alice@example.com".to_string()), FieldValue::from("bob@example.com".to_string())]),
+          "sizes".to_string() => DocumentValue::from(vec![FieldValue::from(1024.0), FieldValue::from(2048.0)]),
+        }).unwrap();
+        segment_builder.add_document_inner(hash_map! {
+          "subject".to_string() => DocumentValue::from("Dinner".to_string()),
+          "recipients".to_string() => DocumentValue::from("bob@example.com".to_string()),
+        }).unwrap();
+        assert!(segment_builder.add_document_inner(hash_map! {
+          "priority".to_string() => DocumentValue::from(vec![FieldValue::from(1.0), FieldValue::from(2.0)]),
+        }).is_err());
+
+        let segment = segment_builder.finalize().unwrap();
+
+        let mut search_index = SearchIndex::new();
+        search_index.register_segment(segment).unwrap();
+
+        let results = search_index.search_inner("recipients:\"bob@example.com\"", SearchOption{fields: vec!["subject".to_string()], limit: 10, ..Default::default()}).unwrap();
+        assert_eq!(2, results.hits.len());
+
+        let results = search_index.search_inner("recipients:\"alice@example.com\"", SearchOption{fields: vec!["subject".to_string()], limit: 10, ..Default::default()}).unwrap();
+        assert_eq!(1, results.hits.len());
+        assert_eq!(vec![Value::Str("alice@example.com".to_string()), Value::Str("bob@example.com".to_string())], results.hits[0].doc.0["recipients"]);
+        assert_eq!(vec![Value::U64(1024), Value::U64(2048)], results.hits[0].doc.0["sizes"]);
+    }
+
+    #[test]
+    fn scores_and_addresses(){
+        let schema = hash_map! {
+                "title".to_string() => FieldPRoperties{text: Some(true), stored: Some(true), ..Default::default()},
+            };
+
+        let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+        segment_builder.add_document_inner(hash_map! {
+          "title".to_string() => "The Old Man and the Sea".to_string(),
+        }).unwrap();
+        let segment1 = segment_builder.finalize().unwrap();
+
+        let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+        segment_builder.add_document_inner(hash_map! {
+          "title".to_string() => "Frankenstein".to_string(),
+        }).unwrap();
+        segment_builder.add_document_inner(hash_map! {
+          "title".to_string() => "The Sea, the Sea".to_string(),
+        }).unwrap();
+        let segment2 = segment_builder.finalize().unwrap();
+
+        let mut search_index = SearchIndex::new();
+        search_index.register_segment(segment1).unwrap();
+        search_index.register_segment(segment2).unwrap();
+
+        let results = search_index.search_inner("sea", SearchOption{fields: vec!["title".to_string()], limit: 10, ..Default::default()}).unwrap();
+        assert_eq!(2, results.hits.len());
+        // "sea" appears twice in the shorter title, it must rank first
+        assert_eq!(vec![Value::Str("The Sea, the Sea".to_string())], results.hits[0].doc.0["title"]);
+        assert_eq!(1, results.hits[0].doc_id);
+        assert_eq!(0, results.hits[1].doc_id);
+        assert!(results.hits[0].score > results.hits[1].score);
+        assert_ne!(results.hits[0].segment_id, results.hits[1].segment_id);
+    }
+
+    #[test]
+    fn offset_and_cursor(){
+        let schema = hash_map! {
+                "id".to_string() => FieldPRoperties{string: Some(true), stored: Some(true), ..Default::default()},
+                "body".to_string() => FieldPRoperties{text: Some(true), ..Default::default()},
+            };
+
+        let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+        for (id, body) in [("0", "sea"), ("1", "sea sea sea"), ("2", "sea sea"), ("3", "sea sea sea sea")] {
+            segment_builder.add_document_inner(hash_map! {
+              "id".to_string() => id.to_string(),
+              "body".to_string() => body.to_string(),
+            }).unwrap();
+        }
+        let segment1 = segment_builder.finalize().unwrap();
+
+        let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+        for (id, body) in [("4", "sea sea sea"), ("5", "the sea")] {
+            segment_builder.add_document_inner(hash_map! {
+              "id".to_string() => id.to_string(),
+              "body".to_string() => body.to_string(),
+            }).unwrap();
+        }
+        let segment2 = segment_builder.finalize().unwrap();
+
+        let mut search_index = SearchIndex::new();
+        search_index.register_segment(segment1).unwrap();
+        search_index.register_segment(segment2).unwrap();
+
+        let ids = |result: &SearchResult| -> Vec<Value> {
+            result.hits.iter().map(|hit| hit.doc.0["id"][0].clone()).collect()
+        };
+
+        let all = search_index.search_inner("sea", SearchOption{fields: vec!["body".to_string()], limit: 10, ..Default::default()}).unwrap();
+        assert_eq!(6, all.hits.len());
+
+        let page = search_index.search_inner("sea", SearchOption{fields: vec!["body".to_string()], limit: 2, offset: Some(2), ..Default::default()}).unwrap();
+        assert_eq!(ids(&all)[2..4], ids(&page)[..]);
+
+        let mut paginated = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = search_index.search_inner("sea", SearchOption{fields: vec!["body".to_string()], limit: 4, cursor, ..Default::default()}).unwrap();
+            if page.hits.is_empty() {
+                break;
+            }
+            paginated.extend(ids(&page));
+            cursor = page.cursor;
+        }
+        assert_eq!(ids(&all), paginated);
+    }
+
+    #[test]
+    fn sort_by_fast_field(){
+        let schema = hash_map! {
+                "subject".to_string() => FieldPRoperties{text: Some(true), stored: Some(true), ..Default::default()},
+                "received".to_string() => FieldPRoperties{field_type: Some(FieldKind::Date), fast: Some(true), ..Default::default()},
+                "size".to_string() => FieldPRoperties{field_type: Some(FieldKind::I64), fast: Some(true), ..Default::default()},
+            };
+
+        let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+        for (subject, received, size) in [
+            ("Meeting notes", "2022-03-01T09:00:00Z", 30.0),
+            ("Meeting moved", "2022-05-01T09:00:00Z", -10.0),
+            ("Meeting cancelled", "2022-04-01T09:00:00Z", 20.0),
+            ("Invoice", "2022-06-01T09:00:00Z", 0.0),
+        ] {
+            segment_builder.add_document_inner(hash_map! {
+              "subject".to_string() => FieldValue::from(subject.to_string()),
+              "received".to_string() => FieldValue::from(received.to_string()),
+              "size".to_string() => FieldValue::from(size),
+            }).unwrap();
+        }
+        let segment = segment_builder.finalize().unwrap();
+
+        let mut search_index = SearchIndex::new();
+        search_index.register_segment(segment).unwrap();
+
+        let results = search_index.search_inner("meeting", SearchOption{
+            fields: vec!["subject".to_string()],
+            limit: 10,
+            sort_by: Some(SortBy{field: "received".to_string(), order: None}),
+            ..Default::default()
+        }).unwrap();
+        let sort_values: Vec<Option<Value>> = results.hits.iter().map(|hit| hit.sort_value.clone()).collect();
+        assert_eq!(vec![
+            Some(Value::Str("2022-05-01T09:00:00Z".to_string())),
+            Some(Value::Str("2022-04-01T09:00:00Z".to_string())),
+            Some(Value::Str("2022-03-01T09:00:00Z".to_string())),
+        ], sort_values);
+        assert_eq!(None, results.cursor);
+
+        let results = search_index.search_inner("meeting", SearchOption{
+            fields: vec!["subject".to_string()],
+            limit: 2,
+            sort_by: Some(SortBy{field: "size".to_string(), order: Some(SortOrder::Asc)}),
+            ..Default::default()
+        }).unwrap();
+        let sort_values: Vec<Option<Value>> = results.hits.iter().map(|hit| hit.sort_value.clone()).collect();
+        assert_eq!(vec![Some(Value::I64(-10)), Some(Value::I64(20))], sort_values);
+
+        assert!(search_index.search_inner("meeting", SearchOption{
+            fields: vec!["subject".to_string()],
+            limit: 10,
+            sort_by: Some(SortBy{field: "subject".to_string(), order: None}),
+            ..Default::default()
+        }).is_err());
+    }
+
+    #[test]
+    fn sort_by_field_not_fast_in_every_segment(){
+        let mut search_index = SearchIndex::new();
+        for fast in [true, false] {
+            let schema = hash_map! {
+                    "size".to_string() => FieldPRoperties{field_type: Some(FieldKind::I64), fast: Some(fast), indexed: Some(true), ..Default::default()},
+                };
+            let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+            segment_builder.add_document_inner(hash_map! {"size".to_string() => FieldValue::from(10.0)}).unwrap();
+            search_index.register_segment(segment_builder.finalize().unwrap()).unwrap();
+        }
+
+        assert!(search_index.search_inner("size:[0 TO 100]", SearchOption{
+            limit: 10,
+            sort_by: Some(SortBy{field: "size".to_string(), order: None}),
+            ..Default::default()
+        }).is_err());
+    }
+
+    #[test]
+    fn highlight(){
+        let schema = hash_map! {
+                "title".to_string() => FieldPRoperties{text: Some(true), stored: Some(true), ..Default::default()},
+                "body".to_string() => FieldPRoperties{text: Some(true), ..Default::default()},
+            };
+
+        let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+        segment_builder.add_document_inner(hash_map! {
+          "title".to_string() => "Café <Old> Man & the Sea".to_string(),
+          "body".to_string() => "He was an old man who fished alone".to_string(),
+        }).unwrap();
+        let segment = segment_builder.finalize().unwrap();
+
+        let mut search_index = SearchIndex::new();
+        search_index.register_segment(segment).unwrap();
+
+        let results = search_index.search_inner("sea", SearchOption{
+            fields: vec!["title".to_string()],
+            limit: 10,
+            highlight: Some(HighlightOption{
+                fields: vec!["title".to_string()],
+                pre_tag: Some("<em>".to_string()),
+                post_tag: Some("</em>".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }).unwrap();
+        let highlight = &results.hits[0].highlights.as_ref().unwrap()["title"];
+        assert_eq!("Café &lt;Old&gt; Man &amp; the <em>Sea</em>", highlight.html);
+        assert_eq!(vec![HighlightRange{start: 21, end: 24}], highlight.ranges);
+
+        // body is not stored, there is no text to highlight
+        assert!(search_index.search_inner("old", SearchOption{
+            fields: vec!["body".to_string()],
+            limit: 10,
+            highlight: Some(HighlightOption{fields: vec!["body".to_string()], ..Default::default()}),
+            ..Default::default()
+        }).is_err());
+    }
+
+    #[test]
+    fn count_and_exists(){
+        let schema = hash_map! {
+                "id".to_string() => FieldPRoperties{string: Some(true), ..Default::default()},
+                "body".to_string() => FieldPRoperties{text: Some(true), ..Default::default()},
+            };
+
+        let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+        for (id, body) in [("0", "unread message"), ("1", "read message"), ("2", "unread invoice")] {
+            segment_builder.add_document_inner(hash_map! {
+              "id".to_string() => id.to_string(),
+              "body".to_string() => body.to_string(),
+            }).unwrap();
+        }
+        let segment1 = segment_builder.finalize().unwrap();
+
+        let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+        segment_builder.remove_documents("id", "2").unwrap();
+        let segment2 = segment_builder.finalize().unwrap();
+
+        let mut merger = Merger::new();
+        merger.add_segment(segment1).unwrap();
+        merger.add_segment(segment2).unwrap();
+
+        let mut search_index = SearchIndex::new();
+        search_index.register_segment(merger.merge().unwrap()).unwrap();
+
+        let option = || QueryOption{fields: vec!["body".to_string()], ..Default::default()};
+        assert_eq!(2, search_index.count_inner("message", option()).unwrap());
+        assert_eq!(1, search_index.count_inner("unread", option()).unwrap());
+        assert!(search_index.exists_inner("read", option()).unwrap());
+        assert!(!search_index.exists_inner("invoice", option()).unwrap());
+
+        let no_fields: QueryOption = serde_json::from_str("{}").unwrap();
+        assert_eq!(1, search_index.count_inner("body:unread", no_fields).unwrap());
+    }
+
+    #[test]
+    fn query_dsl(){
+        let schema = hash_map! {
+                "title".to_string() => FieldPRoperties{text: Some(true), stored: Some(true), ..Default::default()},
+                "folder".to_string() => FieldPRoperties{string: Some(true), ..Default::default()},
+                "received".to_string() => FieldPRoperties{field_type: Some(FieldKind::Date), ..Default::default()},
+            };
+
+        let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+        for (title, folder, received) in [
+            ("The Old Man and the Sea", "inbox", "2022-03-01T09:00:00Z"),
+            ("The Sea, the Sea", "archive", "2021-03-01T09:00:00Z"),
+            ("Frankenstein", "inbox", "2022-04-01T09:00:00Z"),
+            ("The Man Who Laughs", "inbox", "2020-04-01T09:00:00Z"),
+        ] {
+            segment_builder.add_document_inner(hash_map! {
+              "title".to_string() => title.to_string(),
+              "folder".to_string() => folder.to_string(),
+              "received".to_string() => received.to_string(),
+            }).unwrap();
+        }
+        let segment = segment_builder.finalize().unwrap();
+
+        let mut search_index = SearchIndex::new();
+        search_index.register_segment(segment).unwrap();
+
+        let search = |json_query: &str| {
+            let query: QueryDsl = serde_json::from_str(json_query).unwrap();
+            search_index.search_inner(query, SearchOption{limit: 10, ..Default::default()}).unwrap().hits.len()
+        };
+
+        assert_eq!(3, search(r#"{"term": {"field": "folder", "value": "inbox"}}"#));
+        assert_eq!(1, search(r#"{"phrase": {"field": "title", "value": "The old man"}}"#));
+        assert_eq!(2, search(r#"{"range": {"field": "received", "gte": "2022-01-01T00:00:00Z"}}"#));
+        assert_eq!(2, search(r#"{"prefix": {"field": "title", "value": "ma"}}"#));
+        assert_eq!(1, search(r#"{"prefix": {"field": "title", "value": "Fra"}}"#));
+        assert_eq!(2, search(r#"{"fuzzy": {"field": "title", "value": "Mann"}}"#));
+        assert_eq!(4, search(r#"{"all": {}}"#));
+        assert_eq!(1, search(r#"{"bool": {
+            "must": [{"term": {"field": "folder", "value": "inbox"}}, {"term": {"field": "title", "value": "sea"}}]
+        }}"#));
+        assert_eq!(2, search(r#"{"bool": {
+            "mustNot": [{"term": {"field": "title", "value": "the"}}]
+        }}"#) + search(r#"{"bool": {
+            "mustNot": [{"term": {"field": "folder", "value": "inbox"}}]
+        }}"#));
+
+        let query: QueryDsl = serde_json::from_str(r#"{"term": {"field": "author", "value": "Hemingway"}}"#).unwrap();
+        assert!(search_index.search_inner(query, SearchOption{limit: 10, ..Default::default()}).is_err());
+        let query: QueryDsl = serde_json::from_value(json!({"prefix": {"field": "folder", "value": "a".repeat(300)}})).unwrap();
+        assert!(search_index.search_inner(query, SearchOption{limit: 10, ..Default::default()}).is_err());
+
+        let query: QueryDsl = serde_json::from_str(r#"{"all": {}}"#).unwrap();
+        let option: SearchOption = serde_json::from_str(r#"{"limit": 10}"#).unwrap();
+        assert_eq!(4, search_index.search_inner(query, option).unwrap().hits.len());
+    }
+
+    #[test]
+    fn fuzzy_search(){
+        let schema = hash_map! {
+                "subject".to_string() => FieldPRoperties{text: Some(true), ..Default::default()},
+                "body".to_string() => FieldPRoperties{text: Some(true), ..Default::default()},
+            };
+
+        let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+        segment_builder.add_document_inner(hash_map! {
+          "subject".to_string() => "Did you receive my invoice".to_string(),
+          "body".to_string() => "Please confirm the address".to_string(),
+        }).unwrap();
+        let segment = segment_builder.finalize().unwrap();
+
+        let mut search_index = SearchIndex::new();
+        search_index.register_segment(segment).unwrap();
+
+        let fields = || vec!["subject".to_string(), "body".to_string()];
+        assert_eq!(0, search_index.count_inner("recieve", QueryOption{fields: fields(), ..Default::default()}).unwrap());
+        assert_eq!(1, search_index.count_inner("recieve", QueryOption{fields: fields(), fuzzy: Some(FuzzyOption::default()), ..Default::default()}).unwrap());
+        assert_eq!(1, search_index.count_inner("recieve~1", QueryOption{fields: fields(), ..Default::default()}).unwrap());
+        assert_eq!(1, search_index.count_inner("recieve~1 AND adress~1", QueryOption{fields: fields(), ..Default::default()}).unwrap());
+        // without transpositions "recieve" is 2 edits away from "receive"
+        assert_eq!(0, search_index.count_inner("recieve", QueryOption{
+            fields: fields(),
+            fuzzy: Some(FuzzyOption{transpositions: Some(false), ..Default::default()}),
+            ..Default::default()
+        }).unwrap());
+        assert_eq!(1, search_index.count_inner("inv", QueryOption{
+            fields: fields(),
+            fuzzy: Some(FuzzyOption{distance: Some(0), prefix: Some(true), ..Default::default()}),
+            ..Default::default()
+        }).unwrap());
+        assert!(search_index.count_inner("recieve~3", QueryOption{fields: fields(), ..Default::default()}).is_err());
+    }
+
+    #[test]
+    fn fuzzy_search_stemmed_field(){
+        let schema = hash_map! {
+                "subject".to_string() => FieldPRoperties{text: Some(true), tokenizer: Some("en_stem".to_string()), ..Default::default()},
+            };
+
+        let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+        segment_builder.add_document_inner(hash_map! {
+          "subject".to_string() => "Did you receive my invoices".to_string(),
+        }).unwrap();
+        let segment = segment_builder.finalize().unwrap();
+
+        let mut search_index = SearchIndex::new();
+        search_index.register_segment(segment).unwrap();
+
+        let option = || QueryOption{fields: vec!["subject".to_string()], ..Default::default()};
+        // the parser stems "Recieving" to "reciev", 1 transposition away from the indexed "receiv"
+        assert_eq!(0, search_index.count_inner("Recieving", option()).unwrap());
+        assert_eq!(1, search_index.count_inner("Recieving~1", option()).unwrap());
+        assert_eq!(1, search_index.count_inner("subject:Invoicing~1", option()).unwrap());
+    }
+
+    #[test]
+    fn suggest(){
+        let schema = hash_map! {
+                "subject".to_string() => FieldPRoperties{text: Some(true), ..Default::default()},
+                "folder".to_string() => FieldPRoperties{string: Some(true), ..Default::default()},
+            };
+
+        let mut search_index = SearchIndex::new();
+        for subjects in [vec!["Invoice for May", "Invitation to the party"], vec!["Your invoice", "Inventory"]] {
+            let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+            for subject in subjects {
+                segment_builder.add_document_inner(hash_map! {
+                  "subject".to_string() => subject.to_string(),
+                  "folder".to_string() => "Inbox".to_string(),
+                }).unwrap();
+            }
+            search_index.register_segment(segment_builder.finalize().unwrap()).unwrap();
+        }
+
+        let suggestion = |term: &str, doc_freq: u32| Suggestion{term: term.to_string(), doc_freq};
+        assert_eq!(vec![suggestion("invoice", 2), suggestion("inventory", 1), suggestion("invitation", 1)], search_index.suggest_inner("subject", "IN", 10).unwrap());
+        assert_eq!(vec![suggestion("invoice", 2)], search_index.suggest_inner("subject", "my invo", 1).unwrap());
+        assert!(search_index.suggest_inner("subject", "x", 10).unwrap().is_empty());
+        // string fields are not tokenized
+        assert_eq!(vec![suggestion("Inbox", 4)], search_index.suggest_inner("folder", "In", 10).unwrap());
+        assert!(search_index.suggest_inner("folder", "in", 10).unwrap().is_empty());
+        assert!(search_index.suggest_inner("missing", "in", 10).is_err());
+    }
+
+    #[test]
+    fn field_tokenizers(){
+        let schema = hash_map! {
+                "title".to_string() => FieldPRoperties{text: Some(true), tokenizer: Some("fr_stem".to_string()), ..Default::default()},
+                "body".to_string() => FieldPRoperties{text: Some(true), tokenizer: Some("en_stem".to_string()), ..Default::default()},
+                "code".to_string() => FieldPRoperties{text: Some(true), tokenizer: Some("whitespace".to_string()), ..Default::default()},
+            };
+
+        // the tokenizers are registered again on the merged and the searched indexes
+        let mut merger = Merger::new();
+        for _ in 0..2 {
+            let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+            segment_builder.add_document_inner(hash_map! {
+              "title".to_string() => "Les chevaux mangeaient".to_string(),
+              "body".to_string() => "The horses were running".to_string(),
+              "code".to_string() => "INV-2022 paid".to_string(),
+            }).unwrap();
+            merger.add_segment(segment_builder.finalize().unwrap()).unwrap();
+        }
+        let mut search_index = SearchIndex::new();
+        search_index.register_segment(merger.merge().unwrap()).unwrap();
+
+        let count = |query: &str, field: &str| search_index.count_inner(query, QueryOption{fields: vec![field.to_string()], ..Default::default()}).unwrap();
+        assert_eq!(2, count("cheval", "title"));
+        assert_eq!(2, count("mangeait", "title"));
+        assert_eq!(2, count("run", "body"));
+        assert_eq!(2, count("horse", "body"));
+        assert_eq!(2, count("\"INV-2022\"", "code"));
+        assert_eq!(0, count("inv", "code"));
+
+        let unknown_tokenizer = hash_map! {
+                "title".to_string() => FieldPRoperties{text: Some(true), tokenizer: Some("xx_stem".to_string()), ..Default::default()},
+            };
+        assert!(SegmentBuilder::new_inner(&unknown_tokenizer, 50_000_000).is_err());
+        let string_tokenizer = hash_map! {
+                "title".to_string() => FieldPRoperties{string: Some(true), tokenizer: Some("en_stem".to_string()), ..Default::default()},
+            };
+        assert!(SegmentBuilder::new_inner(&string_tokenizer, 50_000_000).is_err());
+    }
+
+    #[test]
+    fn cjk_tokenizer(){
+        let schema = hash_map! {
+                "title".to_string() => FieldPRoperties{text: Some(true), stored: Some(true), tokenizer: Some("cjk".to_string()), ..Default::default()},
+            };
+        let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+        segment_builder.add_document_inner(hash_map! {
+          "title".to_string() => "東京都の天気 Weather".to_string(),
+        }).unwrap();
+        let mut search_index = SearchIndex::new();
+        search_index.register_segment(segment_builder.finalize().unwrap()).unwrap();
+
+        let count = |query: &str| search_index.count_inner(query, QueryOption{fields: vec!["title".to_string()], ..Default::default()}).unwrap();
+        assert_eq!(1, count("東京"));
+        assert_eq!(1, count("京都"));
+        assert_eq!(1, count("天気"));
+        assert_eq!(1, count("weather"));
+        assert_eq!(0, count("京天"));
+        assert_eq!(1, count("東"));
+        assert_eq!(1, count("東京都の天気"));
+    }
+
+    #[test]
+    fn custom_analyzers(){
+        let analyzers: Analyzers = serde_json::from_str(r#"{
+            "name_ngrams": {"tokenizer": {"ngram": {"minGram": 2, "maxGram": 3}}, "filters": ["lowercase", "asciiFolding"]},
+            "folded": {"tokenizer": "simple", "filters": ["lowercase", "asciiFolding", {"stopWords": ["de", "la"]}]}
+        }"#).unwrap();
+        let schema = hash_map! {
+                "name".to_string() => FieldPRoperties{text: Some(true), tokenizer: Some("name_ngrams".to_string()), ..Default::default()},
+                "city".to_string() => FieldPRoperties{text: Some(true), tokenizer: Some("folded".to_string()), ..Default::default()},
+            };
+
+        let mut segment_builder = SegmentBuilder::with_analyzers_inner(&schema, &analyzers, 50_000_000).unwrap();
+        segment_builder.add_document_inner(hash_map! {
+          "name".to_string() => "Zoë Łukasiewicz".to_string(),
+          "city".to_string() => "Aix-la-Chapelle".to_string(),
+        }).unwrap();
+        // the analyzers are rebuilt from the exported segment
+        let segment = Segment::new(&segment_builder.finalize().unwrap().export().unwrap()).unwrap();
+
+        let mut search_index = SearchIndex::new();
+        search_index.register_segment(segment).unwrap();
+
+        let count = |query: &str, field: &str| search_index.count_inner(query, QueryOption{fields: vec![field.to_string()], ..Default::default()}).unwrap();
+        assert_eq!(1, count("zoe", "name"));
+        assert_eq!(1, count("luk", "name"));
+        assert_eq!(1, count("chapelle", "city"));
+        assert_eq!(0, count("la", "city"));
+
+        // the same analyzer name cannot have two definitions in an index
+        let other_analyzers: Analyzers = serde_json::from_str(r#"{
+            "folded": {"tokenizer": "whitespace"}
+        }"#).unwrap();
+        let segment_builder = SegmentBuilder::with_analyzers_inner(&HashMap::new(), &other_analyzers, 50_000_000).unwrap();
+        assert!(search_index.register_segment(segment_builder.finalize().unwrap()).is_err());
+
+        let builtin_name: Analyzers = serde_json::from_str(r#"{"en_stem": {"tokenizer": "raw"}}"#).unwrap();
+        assert!(SegmentBuilder::with_analyzers_inner(&HashMap::new(), &builtin_name, 50_000_000).is_err());
+    }
+
+    #[test]
+    fn analyze_field(){
+        let schema = hash_map! {
+                "title".to_string() => FieldPRoperties{text: Some(true), tokenizer: Some("en_stem".to_string()), ..Default::default()},
+                "id".to_string() => FieldPRoperties{string: Some(true), ..Default::default()},
+                "size".to_string() => FieldPRoperties{field_type: Some(FieldKind::U64), ..Default::default()},
+            };
+        let token = |text: &str, offset_from, offset_to, position| AnalyzedToken{text: text.to_string(), offset_from, offset_to, position};
+
+        let segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+        assert_eq!(vec![token("run", 3, 10, 0), token("dog", 11, 15, 1)], analyze(segment_builder.writer.index(), "title", "🏃 Running Dogs").unwrap());
+        assert_eq!(vec![token("INV-42 A", 0, 8, 0)], analyze(segment_builder.writer.index(), "id", "INV-42 A").unwrap());
+        assert!(analyze(segment_builder.writer.index(), "size", "42").is_err());
+
+        let mut search_index = SearchIndex::new();
+        search_index.register_segment(segment_builder.finalize().unwrap()).unwrap();
+        let (index, _searcher) = search_index.searcher().unwrap();
+        assert_eq!(vec![token("run", 3, 10, 0), token("dog", 11, 15, 1)], analyze(&index, "title", "🏃 Running Dogs").unwrap());
+    }
+
+    #[test]
+    fn synonyms(){
+        let schema = hash_map! {
+                "subject".to_string() => FieldPRoperties{text: Some(true), ..Default::default()},
+                "folder".to_string() => FieldPRoperties{string: Some(true), ..Default::default()},
+            };
+
+        let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+        for (subject, folder) in [("Your bill for May", "bill"), ("Receipt of payment", "billing"), ("Credit note", "billing"), ("Invoices are late", "Invoice")] {
+            segment_builder.add_document_inner(hash_map! {
+              "subject".to_string() => subject.to_string(),
+              "folder".to_string() => folder.to_string(),
+            }).unwrap();
+        }
+        let mut search_index = SearchIndex::new();
+        search_index.register_segment(segment_builder.finalize().unwrap()).unwrap();
+        search_index.set_synonyms_inner(hash_map! {
+            "Invoice".to_string() => vec!["bill".to_string(), "receipt".to_string(), "credit note".to_string()],
+            "credit note".to_string() => vec!["receipt".to_string()],
+        }).unwrap();
+
+        let count = |query: &str, field: &str| search_index.count_inner(query, QueryOption{fields: vec![field.to_string()], ..Default::default()}).unwrap();
+        assert_eq!(3, count("invoice", "subject"));
+        assert_eq!(1, count("bill", "subject"));
+        assert_eq!(2, count("\"credit note\"", "subject"));
+        // consecutive words spell a multi-word synonym, in their order
+        assert_eq!(2, count("credit note", "subject"));
+        assert_eq!(1, count("note credit", "subject"));
+        let conjunction = QueryOption{fields: vec!["subject".to_string()], conjunction: Some(true), ..Default::default()};
+        assert_eq!(2, search_index.count_inner("credit note", conjunction).unwrap());
+        // the synonyms of a string field are not tokenized
+        assert_eq!(2, count("Invoice", "folder"));
+        assert_eq!(0, count("invoice", "folder"));
+        // the word is fuzzy, its alternatives are matched exactly
+        assert_eq!(4, count("invoice~1", "subject"));
+    }
+
+    #[test]
+    fn boosts_and_conjunction(){
+        let schema = hash_map! {
+                "id".to_string() => FieldPRoperties{string: Some(true), stored: Some(true), ..Default::default()},
+                "title".to_string() => FieldPRoperties{text: Some(true), ..Default::default()},
+                "body".to_string() => FieldPRoperties{text: Some(true), ..Default::default()},
+            };
+
+        let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+        for (id, title, body) in [("0", "Meeting notes", "The budget for the project"), ("1", "Project budget", "See the meeting notes")] {
+            segment_builder.add_document_inner(hash_map! {
+              "id".to_string() => id.to_string(),
+              "title".to_string() => title.to_string(),
+              "body".to_string() => body.to_string(),
+            }).unwrap();
+        }
+        let mut search_index = SearchIndex::new();
+        search_index.register_segment(segment_builder.finalize().unwrap()).unwrap();
+
+        let fields = || vec!["title".to_string(), "body".to_string()];
+        let first_id = |query: &str, boosts: HashMap<String, f32>, fuzzy: Option<FuzzyOption>| {
+            let results = search_index.search_inner(query, SearchOption{fields: fields(), limit: 10, boosts: Some(boosts), fuzzy, ..Default::default()}).unwrap();
+            results.hits[0].doc.0["id"][0].as_text().unwrap().to_string()
+        };
+        assert_eq!("0", first_id("budget", hash_map!{"body".to_string() => 3.0}, None));
+        assert_eq!("1", first_id("budget", hash_map!{"title".to_string() => 3.0}, None));
+        // the boosts are kept when the leaves of the query are rewritten
+        assert_eq!("0", first_id("budgt", hash_map!{"body".to_string() => 3.0}, Some(FuzzyOption::default())));
+        assert_eq!("1", first_id("budgt", hash_map!{"title".to_string() => 3.0}, Some(FuzzyOption::default())));
+
+        assert_eq!(2, search_index.count_inner("budget sandwich", QueryOption{fields: fields(), ..Default::default()}).unwrap());
+        assert_eq!(0, search_index.count_inner("budget sandwich", QueryOption{fields: fields(), conjunction: Some(true), ..Default::default()}).unwrap());
+        assert!(search_index.count_inner("budget", QueryOption{fields: fields(), boosts: Some(hash_map!{"missing".to_string() => 2.0}), ..Default::default()}).is_err());
+    }
+
+    #[test]
+    fn more_like_this(){
+        let schema = hash_map! {
+                "id".to_string() => FieldPRoperties{string: Some(true), stored: Some(true), ..Default::default()},
+                "body".to_string() => FieldPRoperties{text: Some(true), stored: Some(true), ..Default::default()},
+            };
+
+        let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+        for (id, body) in [
+            ("0", "The ring passed out of all knowledge, the ring of power"),
+            ("1", "Frodo carried the ring to the mountain"),
+            ("2", "The old man fished alone in the sea"),
+            ("3", "The sea was calm and the old man slept"),
+        ] {
+            segment_builder.add_document_inner(hash_map! {
+              "id".to_string() => id.to_string(),
+              "body".to_string() => body.to_string(),
+            }).unwrap();
+        }
+        let mut search_index = SearchIndex::new();
+        search_index.register_segment(segment_builder.finalize().unwrap()).unwrap();
+
+        let ids = |results: SearchResult| -> Vec<Value> {
+            results.hits.iter().map(|hit| hit.doc.0["id"][0].clone()).collect()
+        };
+        let option = || MoreLikeThisOption{min_term_frequency: Some(1), min_doc_frequency: Some(1), ..Default::default()};
+
+        let results = search_index.search_inner("fished", SearchOption{fields: vec!["body".to_string()], limit: 10, ..Default::default()}).unwrap();
+        let hit_ref = HitRef{segment_id: results.hits[0].segment_id.clone(), doc_id: results.hits[0].doc_id};
+        let similar = ids(search_index.more_like_this_inner(MoreLikeThisSource::Hit(hit_ref), option()).unwrap());
+        assert_eq!(Value::Str("3".to_string()), similar[0]);
+        assert!(!similar.contains(&Value::Str("2".to_string())));
+        // the source document doesn't shift the pages
+        let page = |offset| {
+            let hit_ref = HitRef{segment_id: results.hits[0].segment_id.clone(), doc_id: results.hits[0].doc_id};
+            ids(search_index.more_like_this_inner(MoreLikeThisSource::Hit(hit_ref), MoreLikeThisOption{limit: Some(1), offset: Some(offset), ..option()}).unwrap())
+        };
+        assert_eq!(similar, (0..similar.len()).flat_map(page).collect::<Vec<_>>());
+        let hit: MoreLikeThisSource = serde_json::from_value(serde_json::to_value(&results.hits[0]).unwrap()).unwrap();
+        assert_eq!(similar, ids(search_index.more_like_this_inner(hit, option()).unwrap()));
+
+        let field_values = hash_map! {"body".to_string() => DocumentValue::from("ring of power".to_string())};
+        let similar = ids(search_index.more_like_this_inner(MoreLikeThisSource::Fields(field_values), MoreLikeThisOption{limit: Some(1), ..option()}).unwrap());
+        assert_eq!(vec![Value::Str("0".to_string())], similar);
+
+        let unknown_segment = HitRef{segment_id: "0f4e6cbfc5ab4d3d8f3d3b7c1b2a9e10".to_string(), doc_id: 0};
+        assert!(search_index.more_like_this_inner(MoreLikeThisSource::Hit(unknown_segment), option()).is_err());
+    }
+
+    #[test]
+    fn regex_and_wildcard(){
+        let schema = hash_map! {
+                "sku".to_string() => FieldPRoperties{string: Some(true), ..Default::default()},
+            };
+
+        let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+        for sku in ["INV-2022-001", "INV-2022-002", "INV-2021-001", "CRN-2022-001"] {
+            segment_builder.add_document_inner(hash_map! {
+              "sku".to_string() => sku.to_string(),
+            }).unwrap();
+        }
+        let mut search_index = SearchIndex::new();
+        search_index.register_segment(segment_builder.finalize().unwrap()).unwrap();
+
+        let count = |json_query: &str| {
+            let query: QueryDsl = serde_json::from_str(json_query).unwrap();
+            search_index.count_inner(query, QueryOption::default())
+        };
+        assert_eq!(2, count(r#"{"wildcard": {"field": "sku", "value": "INV-2022-*"}}"#).unwrap());
+        assert_eq!(3, count(r#"{"wildcard": {"field": "sku", "value": "*-2022-00?"}}"#).unwrap());
+        assert_eq!(0, count(r#"{"wildcard": {"field": "sku", "value": "INV-2022"}}"#).unwrap());
+        assert_eq!(2, count(r#"{"regex": {"field": "sku", "pattern": "[A-Z]{3}-2022-001"}}"#).unwrap());
+        assert!(count(r#"{"regex": {"field": "sku", "pattern": "INV-(2022"}}"#).is_err());
+        // the automaton of this pattern has too many states
+        assert!(count(r#"{"regex": {"field": "sku", "pattern": "(a|b)*a(a|b){30}"}}"#).is_err());
+        let too_long = format!(r#"{{"regex": {{"field": "sku", "pattern": "{}"}}}}"#, "a".repeat(300));
+        assert!(count(&too_long).is_err());
+    }
+
+    #[test]
+    fn explain(){
+        let schema = hash_map! {
+                "title".to_string() => FieldPRoperties{text: Some(true), ..Default::default()},
+                "body".to_string() => FieldPRoperties{text: Some(true), ..Default::default()},
+            };
+
+        let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+        for (title, body) in [("The Old Man and the Sea", "He was an old man who fished alone"), ("Frankenstein", "The sea was calm")] {
+            segment_builder.add_document_inner(hash_map! {
+              "title".to_string() => title.to_string(),
+              "body".to_string() => body.to_string(),
+            }).unwrap();
+        }
+        let mut search_index = SearchIndex::new();
+        search_index.register_segment(segment_builder.finalize().unwrap()).unwrap();
+
+        let option = || QueryOption{
+            fields: vec!["title".to_string(), "body".to_string()],
+            boosts: Some(hash_map!{"title".to_string() => 2.0}),
+            ..Default::default()
+        };
+        let results = search_index.search_inner("old sea", SearchOption{limit: 10, ..option().into()}).unwrap();
+        for hit in results.hits.iter() {
+            let hit_ref = HitRef{segment_id: hit.segment_id.clone(), doc_id: hit.doc_id};
+            let explanation = search_index.explain_inner("old sea", &hit_ref, option()).unwrap();
+            assert!((explanation.value() - hit.score).abs() < 1e-5);
+            // a whole hit can be passed as the hit reference
+            let hit_ref: HitRef = serde_json::from_value(serde_json::to_value(hit).unwrap()).unwrap();
+            assert_eq!(explanation.value(), search_index.explain_inner("old sea", &hit_ref, option()).unwrap().value());
+        }
+
+        let hit_ref = HitRef{segment_id: results.hits[0].segment_id.clone(), doc_id: results.hits[0].doc_id};
+        // the document doesn't match the query
+        assert!(search_index.explain_inner("sandwich", &hit_ref, option()).is_err());
+        let hit_ref = HitRef{segment_id: results.hits[0].segment_id.clone(), doc_id: 42};
+        assert!(search_index.explain_inner("old sea", &hit_ref, option()).is_err());
+    }
+
+    #[test]
+    fn lenient_search(){
+        let schema = hash_map! {
+                "title".to_string() => FieldPRoperties{text: Some(true), stored: Some(true), ..Default::default()},
+            };
+
+        let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+        for title in ["The old man and the sea", "The sea wolf"] {
+            segment_builder.add_document_inner(hash_map! {"title".to_string() => title.to_string()}).unwrap();
+        }
+        let mut search_index = SearchIndex::new();
+        search_index.register_segment(segment_builder.finalize().unwrap()).unwrap();
+
+        let option = |lenient| SearchOption{fields: vec!["title".to_string()], limit: 10, lenient, ..Default::default()};
+        let results = search_index.search_inner(r#"wolf title: "old man"#, option(Some(true))).unwrap();
+        assert_eq!(2, results.hits.len());
+        let errors = results.errors.unwrap();
+        assert_eq!(vec![(QueryErrorKind::Syntax, Some(5)), (QueryErrorKind::Syntax, Some(12))], errors.iter().map(|error| (error.kind, error.position)).collect::<Vec<_>>());
+        assert_eq!(Some(vec![]), search_index.search_inner("wolf", option(Some(true))).unwrap().errors);
+        assert!(search_index.search_inner("wolf", option(None)).unwrap().errors.is_none());
+
+        match search_index.search_inner("wolf body:sea", option(None)) {
+            Err(SearchError::Query(error)) => assert_eq!((QueryErrorKind::UnknownField, Some(5)), (error.kind, error.position)),
+            _ => panic!("expected a query error"),
+        }
+        // the positions are the ones in the query string with its fuzzy markers
+        match search_index.search_inner("wolf~1 title:", option(None)) {
+            Err(SearchError::Query(error)) => assert_eq!((QueryErrorKind::Syntax, Some(7)), (error.kind, error.position)),
+            _ => panic!("expected a query error"),
+        }
+        let results = search_index.search_inner("wolf~1 title:", option(Some(true))).unwrap();
+        assert_eq!(vec![Some(7)], results.errors.unwrap().iter().map(|error| error.position).collect::<Vec<_>>());
+        assert_eq!(1, search_index.count_inner("wolf body:sea", QueryOption{fields: vec!["title".to_string()], lenient: Some(true), ..Default::default()}).unwrap());
+    }
+
+    #[test]
+    fn aggregations(){
+        let schema = hash_map! {
+                "subject".to_string() => FieldPRoperties{text: Some(true), ..Default::default()},
+                "sender".to_string() => FieldPRoperties{string: Some(true), fast: Some(true), ..Default::default()},
+                "size".to_string() => FieldPRoperties{field_type: Some(FieldKind::U64), fast: Some(true), ..Default::default()},
+            };
+
+        let mut search_index = SearchIndex::new();
+        let segments = [
+            vec![("Weekly report", "jane", 10.0), ("Monthly report", "john", 250.0), ("Report draft", "jane", 40.0)],
+            vec![("Yearly report", "jane", 120.0), ("Invoice", "ann", 30.0)],
+        ];
+        for documents in segments {
+            let mut segment_builder = SegmentBuilder::new_inner(&schema, 50_000_000).unwrap();
+            for (subject, sender, size) in documents {
+                segment_builder.add_document_inner(hash_map! {
+                  "subject".to_string() => FieldValue::from(subject.to_string()),
+                  "sender".to_string() => FieldValue::from(sender.to_string()),
+                  "size".to_string() => FieldValue::from(size),
+                }).unwrap();
+            }
+            search_index.register_segment(segment_builder.finalize().unwrap()).unwrap();
+        }
+
+        let aggregations: Aggregations = serde_json::from_value(json!({
+            "senders": {"terms": {"field": "sender"}},
+            "sizes": {"histogram": {"field": "size", "interval": 100.0}},
+            "size_ranges": {"range": {"field": "size", "ranges": [{"to": 100.0}, {"from": 100.0}]}},
+            "size_stats": {"stats": {"field": "size"}},
+        })).unwrap();
+        let option = |aggregations| SearchOption{fields: vec!["subject".to_string()], limit: 1, aggregations: Some(aggregations), ..Default::default()};
+        let results = search_index.search_inner("report", option(aggregations)).unwrap();
+        assert_eq!(1, results.hits.len());
+        let aggregations = serde_json::to_value(results.aggregations.unwrap()).unwrap();
+        let doc_counts = |name: &str| aggregations[name]["buckets"].as_array().unwrap().iter().map(|bucket| (bucket["key"].clone(), bucket["doc_count"].clone())).collect::<Vec<_>>();
+        assert_eq!(vec![(json!("jane"), json!(3)), (json!("john"), json!(1))], doc_counts("senders"));
+        assert_eq!(vec![(json!(0.0), json!(2)), (json!(100.0), json!(1)), (json!(200.0), json!(1))], doc_counts("sizes"));
+        assert_eq!(vec![json!(2), json!(2)], doc_counts("size_ranges").into_iter().map(|(_key, doc_count)| doc_count).collect::<Vec<_>>());
+        assert_eq!((json!(4), json!(10.0), json!(250.0), json!(105.0)), (aggregations["size_stats"]["count"].clone(), aggregations["size_stats"]["min"].clone(), aggregations["size_stats"]["max"].clone(), aggregations["size_stats"]["avg"].clone()));
+
+        assert!(search_index.search_inner("report", SearchOption{fields: vec!["subject".to_string()], limit: 10, ..Default::default()}).unwrap().aggregations.is_none());
+        let not_fast: Aggregations = serde_json::from_value(json!({"subjects": {"terms": {"field": "subject"}}})).unwrap();
+        assert!(search_index.search_inner("report", option(not_fast)).is_err());
     }
 
 }
@@ -0,0 +1,339 @@
+use std::ops::Bound;
+
+use serde::Deserialize;
+use wasm_bindgen::JsValue;
+
+use tantivy::{
+    query::{AllQuery, BooleanQuery, BoostQuery, FuzzyTermQuery, Occur, PhraseQuery, Query, RangeQuery, RegexQuery, TermQuery},
+    schema::{Field, FieldType, IndexRecordOption},
+    tokenizer::TokenStream,
+    Index as TantivyIndex, Term,
+};
+
+use crate::errors::WasmInterfaceError;
+use crate::new_api::FieldValue;
+use crate::query_rewrite::{tokenize, MAX_FUZZY_DISTANCE};
+
+// A query sent from JS: either a string in the QueryParser syntax or a structured query object.
+pub enum SearchQuery {
+    Text(String),
+    Dsl(QueryDsl),
+}
+
+impl SearchQuery {
+    pub fn from_js(js_query: JsValue) -> Result<SearchQuery, String> {
+        match js_query.as_string() {
+            Some(text) => Ok(SearchQuery::Text(text)),
+            None => Ok(SearchQuery::Dsl(serde_wasm_bindgen::from_value(js_query).map_err(|err| err.to_string())?)),
+        }
+    }
+}
+
+impl From<&str> for SearchQuery {
+    fn from(text: &str) -> Self {
+        SearchQuery::Text(text.to_string())
+    }
+}
+
+impl From<QueryDsl> for SearchQuery {
+    fn from(query: QueryDsl) -> Self {
+        SearchQuery::Dsl(query)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum QueryDsl {
+    Bool(BoolQueryDsl),
+    Term(TermQueryDsl),
+    Phrase(PhraseQueryDsl),
+    Range(RangeQueryDsl),
+    Prefix(PrefixQueryDsl),
+    Fuzzy(FuzzyQueryDsl),
+    Regex(RegexQueryDsl),
+    Wildcard(WildcardQueryDsl),
+    All(AllQueryDsl),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct BoolQueryDsl {
+    must: Option<Vec<QueryDsl>>,
+    should: Option<Vec<QueryDsl>>,
+    must_not: Option<Vec<QueryDsl>>,
+    boost: Option<f32>,
+}
+
+// Matches the exact term, the value is not tokenized
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TermQueryDsl {
+    field: String,
+    value: FieldValue,
+    boost: Option<f32>,
+}
+
+// The text is tokenized with the tokenizer of the field
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PhraseQueryDsl {
+    field: String,
+    value: String,
+    slop: Option<u32>,
+    boost: Option<f32>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RangeQueryDsl {
+    field: String,
+    gt: Option<FieldValue>,
+    gte: Option<FieldValue>,
+    lt: Option<FieldValue>,
+    lte: Option<FieldValue>,
+    boost: Option<f32>,
+}
+
+// A single word, tokenized with the tokenizer of the field like the fuzzy query value
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PrefixQueryDsl {
+    field: String,
+    value: String,
+    boost: Option<f32>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct FuzzyQueryDsl {
+    field: String,
+    value: String,
+    // Levenshtein distance, defaults to 1
+    distance: Option<u8>,
+    // count a transposition as a single edit, defaults to true
+    transpositions: Option<bool>,
+    // match the terms starting with a word at the given distance
+    prefix: Option<bool>,
+    boost: Option<f32>,
+}
+
+// The pattern must match a whole indexed term, e.g. `INV-20[0-9]{2}-.*` on a `string` field
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RegexQueryDsl {
+    field: String,
+    pattern: String,
+    boost: Option<f32>,
+}
+
+// `*` matches any sequence of characters and `?` a single character, e.g. `INV-2022-*`
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct WildcardQueryDsl {
+    field: String,
+    value: String,
+    boost: Option<f32>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AllQueryDsl {
+    boost: Option<f32>,
+}
+
+impl QueryDsl {
+    pub fn to_query(&self, index: &TantivyIndex) -> Result<Box<dyn Query>, String> {
+        let (query, boost): (Box<dyn Query>, Option<f32>) = match self {
+            QueryDsl::Bool(bool_query) => {
+                let mut clauses = Vec::new();
+                for (occur, queries) in [(Occur::Must, &bool_query.must), (Occur::Should, &bool_query.should), (Occur::MustNot, &bool_query.must_not)] {
+                    for query in queries.iter().flatten() {
+                        clauses.push((occur, query.to_query(index)?));
+                    }
+                }
+                // a boolean query with only `mustNot` clauses matches nothing, exclude the documents from all documents instead
+                if clauses.iter().all(|(occur, _)| *occur == Occur::MustNot) {
+                    clauses.push((Occur::Must, Box::new(AllQuery)));
+                }
+                (Box::new(BooleanQuery::new(clauses)), bool_query.boost)
+            },
+            QueryDsl::Term(term_query) => {
+                let field = get_field(index, &term_query.field)?;
+                let term = to_term(index, field, &term_query.field, &term_query.value)?;
+                (Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs)), term_query.boost)
+            },
+            QueryDsl::Phrase(phrase_query) => {
+                let field = get_text_field(index, &phrase_query.field)?;
+                let tokenizer = index.tokenizer_for_field(field).map_err(|err| err.to_string())?;
+                let mut terms = Vec::new();
+                let mut token_stream = tokenizer.token_stream(&phrase_query.value);
+                token_stream.process(&mut |token| {
+                    terms.push((token.position, Term::from_field_text(field, &token.text)));
+                });
+                let query: Box<dyn Query> = match terms.len() {
+                    // the text only contains characters dropped by the tokenizer
+                    0 => Box::new(BooleanQuery::new(Vec::new())),
+                    1 => Box::new(TermQuery::new(terms.remove(0).1, IndexRecordOption::WithFreqs)),
+                    _ => {
+                        let indexing_options = index.schema().get_field_entry(field).field_type().get_index_record_option();
+                        if !indexing_options.map_or(false, |option| option.has_positions()) {
+                            return Err(WasmInterfaceError::NoPositions(phrase_query.field.clone()).to_string());
+                        }
+                        let mut query = PhraseQuery::new_with_offset(terms);
+                        query.set_slop(phrase_query.slop.unwrap_or_default());
+                        Box::new(query)
+                    },
+                };
+                (query, phrase_query.boost)
+            },
+            QueryDsl::Range(range_query) => {
+                let field = get_field(index, &range_query.field)?;
+                let bound = |included: &Option<FieldValue>, excluded: &Option<FieldValue>| -> Result<Bound<Term>, String> {
+                    match (included, excluded) {
+                        (Some(_), Some(_)) => Err(WasmInterfaceError::InvalidRange(range_query.field.clone()).to_string()),
+                        (Some(value), None) => Ok(Bound::Included(to_term(index, field, &range_query.field, value)?)),
+                        (None, Some(value)) => Ok(Bound::Excluded(to_term(index, field, &range_query.field, value)?)),
+                        (None, None) => Ok(Bound::Unbounded),
+                    }
+                };
+                let lower_bound = bound(&range_query.gte, &range_query.gt)?;
+                let upper_bound = bound(&range_query.lte, &range_query.lt)?;
+                let value_type = index.schema().get_field_entry(field).field_type().value_type();
+                (Box::new(RangeQuery::new_term_bounds(field, value_type, &lower_bound, &upper_bound)), range_query.boost)
+            },
+            QueryDsl::Prefix(prefix_query) => {
+                let field = get_text_field(index, &prefix_query.field)?;
+                let query: Box<dyn Query> = match normalize_word(index, field, &prefix_query.value)? {
+                    Some(prefix) => regex(field, &prefix_query.field, &format!("{}.*", escape_regex(&prefix)))?,
+                    None => Box::new(BooleanQuery::new(Vec::new())),
+                };
+                (query, prefix_query.boost)
+            },
+            QueryDsl::Fuzzy(fuzzy_query) => {
+                let field = get_text_field(index, &fuzzy_query.field)?;
+                let distance = fuzzy_query.distance.unwrap_or(1);
+                if distance > MAX_FUZZY_DISTANCE {
+                    return Err(WasmInterfaceError::InvalidFuzzyDistance(distance).to_string());
+                }
+                let transpositions = fuzzy_query.transpositions.unwrap_or(true);
+                let query: Box<dyn Query> = match normalize_word(index, field, &fuzzy_query.value)? {
+                    Some(word) if fuzzy_query.prefix.unwrap_or_default() => Box::new(FuzzyTermQuery::new_prefix(Term::from_field_text(field, &word), distance, transpositions)),
+                    Some(word) => Box::new(FuzzyTermQuery::new(Term::from_field_text(field, &word), distance, transpositions)),
+                    None => Box::new(BooleanQuery::new(Vec::new())),
+                };
+                (query, fuzzy_query.boost)
+            },
+            QueryDsl::Regex(regex_query) => {
+                let field = get_text_field(index, &regex_query.field)?;
+                (regex(field, &regex_query.field, &regex_query.pattern)?, regex_query.boost)
+            },
+            QueryDsl::Wildcard(wildcard_query) => {
+                let field = get_text_field(index, &wildcard_query.field)?;
+                (regex(field, &wildcard_query.field, &wildcard_to_regex(&wildcard_query.value))?, wildcard_query.boost)
+            },
+            QueryDsl::All(all_query) => (Box::new(AllQuery), all_query.boost),
+        };
+
+        match boost {
+            Some(boost) => Ok(Box::new(BoostQuery::new(query, boost))),
+            None => Ok(query),
+        }
+    }
+}
+
+fn get_field(index: &TantivyIndex, field_name: &str) -> Result<Field, String> {
+    index.schema().get_field(field_name).ok_or_else(||{WasmInterfaceError::InvalidField(field_name.to_string()).to_string()})
+}
+
+pub fn get_text_field(index: &TantivyIndex, field_name: &str) -> Result<Field, String> {
+    let field = get_field(index, field_name)?;
+    match index.schema().get_field_entry(field).field_type() {
+        FieldType::Str(_) => Ok(field),
+        _ => Err(WasmInterfaceError::NotATextField(field_name.to_string()).to_string()),
+    }
+}
+
+// The value of a prefix or fuzzy query tokenized like the indexed text of the field, e.g. lowercased or stemmed.
+// None when the tokenizer drops it, the query then matches nothing.
+fn normalize_word(index: &TantivyIndex, field: Field, word: &str) -> Result<Option<String>, String> {
+    let tokenizer = index.tokenizer_for_field(field).map_err(|err| err.to_string())?;
+    Ok(tokenize(&tokenizer, word).pop().map(|(_, text)| text))
+}
+
+// Builds the term of a value for the type of the field
+fn to_term(index: &TantivyIndex, field: Field, field_name: &str, value: &FieldValue) -> Result<Term, String> {
+    let term = match (index.schema().get_field_entry(field).field_type(), value) {
+        (FieldType::Str(_), FieldValue::Text(text)) => Term::from_field_text(field, text),
+        (FieldType::Str(_), FieldValue::Number(number)) => Term::from_field_text(field, &number.to_string()),
+        (FieldType::U64(_), value) => Term::from_field_u64(field, value.as_u64(field_name)?),
+        (FieldType::I64(_), value) => Term::from_field_i64(field, value.as_i64(field_name)?),
+        (FieldType::F64(_), value) => Term::from_field_f64(field, value.as_f64(field_name)?),
+        (FieldType::Date(_), value) => Term::from_field_date(field, value.as_date(field_name)?),
+        (FieldType::Facet(_), value) => Term::from_facet(field, &value.as_facet(field_name)?),
+        _ => return Err(WasmInterfaceError::UnsupportedFieldType(field_name.to_string()).to_string()),
+    };
+    Ok(term)
+}
+
+// The automaton of a pattern is compiled before the search: its size is bounded so that a pathological pattern
+// fails instead of blocking the thread. The automaton builder of tantivy also fails when it gets too big.
+const MAX_PATTERN_LENGTH: usize = 256;
+
+fn regex(field: Field, field_name: &str, pattern: &str) -> Result<Box<dyn Query>, String> {
+    if pattern.len() > MAX_PATTERN_LENGTH {
+        return Err(WasmInterfaceError::InvalidPattern(field_name.to_string(), format!("it is longer than {} bytes", MAX_PATTERN_LENGTH)).to_string());
+    }
+    let query = RegexQuery::from_pattern(pattern, field).map_err(|err| WasmInterfaceError::InvalidPattern(field_name.to_string(), err.to_string()).to_string())?;
+    Ok(Box::new(query))
+}
+
+pub fn wildcard_to_regex(value: &str) -> String {
+    let mut pattern = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            _ => pattern.push_str(&escape_regex(&c.to_string())),
+        }
+    }
+    pattern
+}
+
+pub fn escape_regex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.+*?()|[]{}^$#&-~".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape_regex, wildcard_to_regex, QueryDsl};
+
+    #[test]
+    fn deserialize() {
+        let query: QueryDsl = serde_json::from_str(r#"{
+            "bool": {
+                "must": [{"term": {"field": "folder", "value": "/inbox"}}],
+                "should": [{"phrase": {"field": "body", "value": "old man", "boost": 2.0}}, {"all": {}}],
+                "mustNot": [{"range": {"field": "received", "lt": "2022-01-01T00:00:00Z"}}]
+            }
+        }"#).unwrap();
+        assert!(matches!(query, QueryDsl::Bool(_)));
+
+        let error = serde_json::from_str::<QueryDsl>(r#"{"term": {"field": "body", "value": "sea", "boots": 2.0}}"#).unwrap_err();
+        assert!(error.to_string().contains("unknown field `boots`"));
+        let error = serde_json::from_str::<QueryDsl>(r#"{"match": {"field": "body", "value": "sea"}}"#).unwrap_err();
+        assert!(error.to_string().contains("unknown variant `match`"));
+    }
+
+    #[test]
+    fn escape() {
+        assert_eq!(r"INV\-2022\.\*", escape_regex("INV-2022.*"));
+        assert_eq!(r"INV\-2022\-.*\..", wildcard_to_regex("INV-2022-*.?"));
+    }
+}
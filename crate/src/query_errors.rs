@@ -0,0 +1,351 @@
+use serde::Serialize;
+use wasm_bindgen::JsValue;
+
+use tantivy::query::{BooleanQuery, Occur, Query, QueryParser, QueryParserError};
+
+use crate::query_rewrite::FuzzyWord;
+use crate::utils::utf16_len;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum QueryErrorKind {
+    Syntax,
+    UnknownField,
+    // the field exists but can't be searched this way, e.g. a phrase on a field without positions
+    InvalidField,
+    InvalidValue,
+    Unsupported,
+}
+
+// An error of a query string, sent to JS as an object
+#[derive(Serialize, Debug, PartialEq)]
+pub struct QueryError {
+    pub kind: QueryErrorKind,
+    pub message: String,
+    // where the error is in the query string, when it can be located
+    pub position: Option<usize>,
+}
+
+// The errors of a search: the errors of the query string are reported as `QueryError` objects, the others as strings
+#[derive(Debug)]
+pub enum SearchError {
+    Query(QueryError),
+    Other(String),
+}
+
+impl From<String> for SearchError {
+    fn from(message: String) -> Self {
+        SearchError::Other(message)
+    }
+}
+
+impl From<SearchError> for JsValue {
+    fn from(error: SearchError) -> Self {
+        match error {
+            SearchError::Query(query_error) => serde_wasm_bindgen::to_value(&query_error).unwrap_or_else(|err| JsValue::from_str(&err.to_string())),
+            SearchError::Other(message) => JsValue::from_str(&message),
+        }
+    }
+}
+
+// A query string as given to the parser: the original one without the fuzzy markers (`word~N`) the parser doesn't support.
+// The errors are located in the parsed query and reported at their position in the original one.
+pub struct QueryText<'a> {
+    original: &'a str,
+    parsed: &'a str,
+    // byte offsets in the parsed query where markers were removed, with their byte lengths
+    removed_markers: Vec<(usize, usize)>,
+}
+
+impl<'a> QueryText<'a> {
+    pub fn new(original: &'a str, parsed: &'a str, fuzzy_words: &[FuzzyWord]) -> QueryText<'a> {
+        let mut removed_len = 0;
+        let removed_markers = fuzzy_words.iter().map(|fuzzy_word|{
+            let removed_marker = (fuzzy_word.marker.start - removed_len, fuzzy_word.marker.len());
+            removed_len += fuzzy_word.marker.len();
+            removed_marker
+        }).collect();
+        QueryText { original, parsed, removed_markers }
+    }
+
+    // JS string index in the original query of a byte offset in the parsed query
+    fn js_index(&self, offset: usize) -> usize {
+        let removed_len: usize = self.removed_markers.iter()
+            .take_while(|(marker_offset, _)| *marker_offset <= offset)
+            .map(|(_, marker_len)| marker_len)
+            .sum();
+        utf16_len(&self.original[..offset + removed_len])
+    }
+
+    pub fn error(&self, error: &QueryParserError) -> QueryError {
+        QueryError::new(self.parsed, 0, error, self)
+    }
+}
+
+impl<'a> From<&'a str> for QueryText<'a> {
+    fn from(query: &'a str) -> Self {
+        QueryText::new(query, query, &[])
+    }
+}
+
+impl QueryError {
+    // `query` is a part of the parsed query starting at the byte offset `offset`
+    fn new(query: &str, offset: usize, error: &QueryParserError, text: &QueryText) -> QueryError {
+        let (kind, field) = match error {
+            QueryParserError::SyntaxError(_) => (QueryErrorKind::Syntax, None),
+            QueryParserError::FieldDoesNotExist(field) => (QueryErrorKind::UnknownField, Some(field)),
+            QueryParserError::FieldNotIndexed(field)
+            | QueryParserError::FieldDoesNotHavePositionsIndexed(field) => (QueryErrorKind::InvalidField, Some(field)),
+            QueryParserError::ExpectedInt(_)
+            | QueryParserError::ExpectedFloat(_)
+            | QueryParserError::DateFormatError(_)
+            | QueryParserError::FacetFormatError(_) => (QueryErrorKind::InvalidValue, None),
+            _ => (QueryErrorKind::Unsupported, None),
+        };
+        // the parser doesn't tell where the error is: the field or the syntax error is looked up in the query
+        let position = match field {
+            Some(field) => find_field(query, field),
+            None if kind == QueryErrorKind::Syntax => find_syntax_error(query),
+            None => None,
+        };
+        QueryError {
+            kind,
+            message: error.to_string(),
+            position: position.map(|position| text.js_index(offset + position)),
+        }
+    }
+}
+
+// Byte position of a `field:` starting a word, outside of the phrases
+fn find_field(query: &str, field_name: &str) -> Option<usize> {
+    let prefix = format!("{}:", field_name);
+    let mut in_quotes = false;
+    let mut previous = None;
+    for (position, c) in query.char_indices() {
+        let is_word_start = previous.map_or(true, |previous: char| previous.is_whitespace() || "(+-".contains(previous));
+        if !in_quotes && is_word_start && query[position..].starts_with(&prefix) {
+            return Some(position);
+        }
+        if c == '"' {
+            in_quotes = !in_quotes;
+        }
+        previous = Some(c);
+    }
+    None
+}
+
+// Byte position of an unbalanced quote or parenthesis, or of a field without a value
+fn find_syntax_error(query: &str) -> Option<usize> {
+    let mut quote_start = None;
+    let mut open_parentheses = Vec::new();
+    let mut word_start = 0;
+    let mut chars = query.char_indices().peekable();
+    while let Some((position, c)) = chars.next() {
+        if quote_start.is_some() {
+            if c == '"' {
+                quote_start = None;
+            }
+            continue;
+        }
+        match c {
+            '"' => quote_start = Some(position),
+            '(' => open_parentheses.push(position),
+            ')' if open_parentheses.pop().is_none() => return Some(position),
+            ':' if chars.peek().map_or(true, |(_, next)| next.is_whitespace() || *next == ')') => return Some(word_start),
+            _ => {},
+        }
+        if c.is_whitespace() || c == '(' || c == ')' {
+            word_start = position + c.len_utf8();
+        }
+    }
+    quote_start.or_else(|| open_parentheses.first().copied())
+}
+
+// The operators between two clauses
+const BINARY_OPERATORS: [&str; 2] = ["AND", "OR"];
+
+// Splits a query string on the whitespaces outside of phrases and parentheses, with the byte position of each word
+fn split_words(query: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut clause_start = None;
+    let mut in_quotes = false;
+    let mut depth = 0usize;
+    for (position, c) in query.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '(' if !in_quotes => depth += 1,
+            ')' if !in_quotes => depth = depth.saturating_sub(1),
+            _ => {},
+        }
+        if c.is_whitespace() && !in_quotes && depth == 0 {
+            if let Some(start) = clause_start.take() {
+                words.push((start, &query[start..position]));
+            }
+        } else if clause_start.is_none() {
+            clause_start = Some(position);
+        }
+    }
+    if let Some(start) = clause_start {
+        words.push((start, &query[start..]));
+    }
+    words
+}
+
+// Splits a query string in clauses that can be parsed on their own, with the byte position of each clause.
+// The `AND`, `OR` and `NOT` operators stay in the clause of the words they apply to.
+fn split_clauses(query: &str) -> Vec<(usize, &str)> {
+    let mut clauses: Vec<(usize, &str)> = Vec::new();
+    let mut joins_next = false;
+    for (start, word) in split_words(query) {
+        let is_binary_operator = BINARY_OPERATORS.contains(&word);
+        match clauses.last_mut() {
+            Some((clause_start, clause)) if joins_next || is_binary_operator => *clause = &query[*clause_start..start + word.len()],
+            _ => clauses.push((start, word)),
+        }
+        joins_next = is_binary_operator || word == "NOT";
+    }
+    clauses
+}
+
+// Removes the operators missing an operand at the ends of a clause
+fn trim_operators(clause: &str) -> &str {
+    let words = split_words(clause);
+    let first = words.iter().position(|(_, word)| !BINARY_OPERATORS.contains(word));
+    let last = words.iter().rposition(|(_, word)| !BINARY_OPERATORS.contains(word) && *word != "NOT");
+    match (first, last) {
+        (Some(first), Some(last)) if first <= last => &clause[words[first].0..words[last].0 + words[last].1.len()],
+        _ => "",
+    }
+}
+
+// Closes the quotes and parentheses of a clause and removes a dangling `field:` or operator
+fn repair(clause: &str) -> Option<String> {
+    let mut repaired = trim_operators(clause).trim_end_matches(':').to_string();
+    if repaired.matches('"').count() % 2 == 1 {
+        repaired.push('"');
+    }
+    let open_parentheses = repaired.matches('(').count();
+    let closed_parentheses = repaired.matches(')').count();
+    if open_parentheses > closed_parentheses {
+        repaired.push_str(&")".repeat(open_parentheses - closed_parentheses));
+    } else if closed_parentheses > open_parentheses {
+        repaired = repaired.replace(['(', ')'], "");
+    }
+    Some(repaired).filter(|repaired| repaired != clause && !repaired.trim().is_empty())
+}
+
+// Parses what can be parsed of a query string instead of failing: the clauses that can't be parsed are repaired,
+// or dropped when they can't be. Returns the errors met on the way.
+pub fn parse_lenient(query_parser: &QueryParser, text: &QueryText) -> (Box<dyn Query>, Vec<QueryError>) {
+    let query = text.parsed;
+    let query_error = match query_parser.parse_query(query) {
+        Ok(parsed_query) => return (parsed_query, Vec::new()),
+        Err(err) => text.error(&err),
+    };
+
+    let mut errors = Vec::new();
+    let mut kept_clauses = Vec::new();
+    for (clause_start, clause) in split_clauses(query) {
+        let err = match query_parser.parse_query(clause) {
+            Ok(_) => {
+                kept_clauses.push(clause.to_string());
+                continue;
+            },
+            Err(err) => err,
+        };
+        let mut clause_error = QueryError::new(clause, clause_start, &err, text);
+        // an error that can't be located in a clause is reported at its start
+        clause_error.position = clause_error.position.or_else(|| Some(text.js_index(clause_start)));
+        errors.push(clause_error);
+        if let Some(repaired) = repair(clause).filter(|repaired| query_parser.parse_query(repaired).is_ok()) {
+            kept_clauses.push(repaired);
+        }
+    }
+    // each clause is valid, the error comes from how they are combined
+    if errors.is_empty() {
+        errors.push(query_error);
+    }
+
+    let parsed_query = query_parser.parse_query(&kept_clauses.join(" ")).unwrap_or_else(|_err|{
+        let clauses = kept_clauses.iter()
+            .filter_map(|clause| query_parser.parse_query(clause).ok())
+            .map(|clause_query| (Occur::Should, clause_query))
+            .collect();
+        Box::new(BooleanQuery::new(clauses))
+    });
+    (parsed_query, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::{query::QueryParser, schema::{Schema, TEXT}, Index};
+
+    use crate::query_rewrite::extract_fuzzy_words;
+    use super::{find_field, find_syntax_error, parse_lenient, split_clauses, trim_operators, QueryErrorKind, QueryText};
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(Some(4), find_syntax_error(r#"sea "old man"#));
+        assert_eq!(Some(4), find_syntax_error("sea title: man"));
+        assert_eq!(Some(3), find_syntax_error("sea) man"));
+        assert_eq!(Some(0), find_syntax_error("(sea man"));
+        assert_eq!(None, find_syntax_error(r#"title:sea "old: man""#));
+    }
+
+    #[test]
+    fn fields() {
+        assert_eq!(Some(26), find_field(r#"subtitle:sea "title: old" title:man"#, "title"));
+        assert_eq!(Some(5), find_field("sea (title:man OR -title:old)", "title"));
+        assert_eq!(Some(1), find_field("+title:man", "title"));
+        assert_eq!(None, find_field("subtitle:man", "title"));
+    }
+
+    #[test]
+    fn clauses() {
+        assert_eq!(vec![(0, "sea"), (4, r#"title:"old man""#), (20, "(a OR b)")], split_clauses(r#"sea title:"old man"  (a OR b)"#));
+        assert_eq!(vec![(0, "invoice AND paid"), (17, r#""x"#)], split_clauses(r#"invoice AND paid "x"#));
+        assert_eq!(vec![(0, "sea"), (4, "NOT old OR  man AND")], split_clauses("sea NOT old OR  man AND"));
+        assert_eq!("old OR man", trim_operators("AND old OR man NOT"));
+        assert_eq!("", trim_operators("AND"));
+    }
+
+    #[test]
+    fn lenient() {
+        let mut schema_builder = Schema::builder();
+        let title = schema_builder.add_text_field("title", TEXT);
+        let index = Index::create_in_ram(schema_builder.build());
+        let query_parser = QueryParser::for_index(&index, vec![title]);
+
+        let (_query, errors) = parse_lenient(&query_parser, &"old man".into());
+        assert!(errors.is_empty());
+
+        let (query, errors) = parse_lenient(&query_parser, &r#"sea "old man"#.into());
+        assert_eq!(1, errors.len());
+        assert_eq!(QueryErrorKind::Syntax, errors[0].kind);
+        assert_eq!(Some(4), errors[0].position);
+        assert_eq!(format!("{:?}", query_parser.parse_query(r#"sea "old man""#).unwrap()), format!("{:?}", query));
+
+        let (query, errors) = parse_lenient(&query_parser, &"sea title: body:man".into());
+        assert_eq!(vec![QueryErrorKind::Syntax, QueryErrorKind::UnknownField], errors.iter().map(|error| error.kind).collect::<Vec<_>>());
+        assert_eq!(vec![Some(4), Some(11)], errors.iter().map(|error| error.position).collect::<Vec<_>>());
+        assert_eq!(format!("{:?}", query_parser.parse_query("sea title").unwrap()), format!("{:?}", query));
+
+        // the operators are kept with the clauses they join
+        let (query, errors) = parse_lenient(&query_parser, &r#"invoice AND paid "x"#.into());
+        assert_eq!(vec![QueryErrorKind::Syntax], errors.iter().map(|error| error.kind).collect::<Vec<_>>());
+        assert_eq!(vec![Some(17)], errors.iter().map(|error| error.position).collect::<Vec<_>>());
+        assert_eq!(format!("{:?}", query_parser.parse_query(r#"invoice AND paid "x""#).unwrap()), format!("{:?}", query));
+
+        let (query, errors) = parse_lenient(&query_parser, &"sea OR man AND".into());
+        assert_eq!(1, errors.len());
+        assert_eq!(format!("{:?}", query_parser.parse_query("sea OR man").unwrap()), format!("{:?}", query));
+
+        // the positions are in the original query, with its fuzzy markers
+        let original = "recieve~1 title:";
+        let (parsed, fuzzy_words) = extract_fuzzy_words(original);
+        let (_query, errors) = parse_lenient(&query_parser, &QueryText::new(original, &parsed, &fuzzy_words));
+        assert_eq!(vec![Some(10)], errors.iter().map(|error| error.position).collect::<Vec<_>>());
+        let error = QueryText::new(original, &parsed, &fuzzy_words).error(&query_parser.parse_query(&parsed).unwrap_err());
+        assert_eq!(Some(10), error.position);
+    }
+}
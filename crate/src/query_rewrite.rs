@@ -0,0 +1,294 @@
+use std::{collections::HashMap, ops::Range};
+
+use tantivy::{
+    query::{BooleanQuery, Occur, PhraseQuery, Query, RangeQuery, TermQuery},
+    schema::{Field, FieldType, IndexRecordOption},
+    tokenizer::{TextAnalyzer, TokenStream},
+    Index as TantivyIndex, Term,
+};
+
+// Rebuilds a query, replacing the leaves of its boolean clauses for which `rewrite` returns a new query.
+// Leaves wrapped in another kind of query (e.g. a `BoostQuery`) are kept as is.
+pub fn rewrite_leaves(query: &dyn Query, rewrite: &mut dyn FnMut(&dyn Query) -> Option<Box<dyn Query>>) -> Box<dyn Query> {
+    if let Some(boolean_query) = query.downcast_ref::<BooleanQuery>() {
+        let clauses = boolean_query.clauses()
+            .iter()
+            .map(|(occur, sub_query)| (*occur, rewrite_leaves(sub_query.as_ref(), rewrite)))
+            .collect();
+        return Box::new(BooleanQuery::new(clauses));
+    }
+    rewrite(query).unwrap_or_else(|| query.box_clone())
+}
+
+// The field of the term or phrase queries produced by the QueryParser
+pub fn leaf_field(leaf: &dyn Query) -> Option<Field> {
+    if let Some(term_query) = leaf.downcast_ref::<TermQuery>() {
+        Some(term_query.term().field())
+    } else if let Some(phrase_query) = leaf.downcast_ref::<PhraseQuery>() {
+        Some(phrase_query.field())
+    } else if let Some(range_query) = leaf.downcast_ref::<RangeQuery>() {
+        Some(range_query.field())
+    } else {
+        None
+    }
+}
+
+// Alternatives of words or phrases, e.g. `{"invoice": ["bill", "receipt"]}`. A word doesn't match its alternatives
+// the other way around unless the dictionary says so.
+pub type Synonyms = HashMap<String, Vec<String>>;
+
+// The synonyms tokenized like the indexed text of each text field, to match the terms and phrases of a parsed query
+pub struct SynonymExpander {
+    expansions: HashMap<(Field, Vec<String>), Vec<Box<dyn Query>>>,
+}
+
+impl SynonymExpander {
+    pub fn empty() -> SynonymExpander {
+        SynonymExpander { expansions: HashMap::new() }
+    }
+
+    pub fn new(index: &TantivyIndex, synonyms: &Synonyms) -> Result<SynonymExpander, String> {
+        let mut expansions: HashMap<(Field, Vec<String>), Vec<Box<dyn Query>>> = HashMap::new();
+        if synonyms.is_empty() {
+            return Ok(SynonymExpander::empty());
+        }
+        let schema = index.schema();
+        for (field, field_entry) in schema.fields() {
+            let indexing_options = match field_entry.field_type() {
+                FieldType::Str(text_options) => match text_options.get_indexing_options() {
+                    Some(indexing_options) => indexing_options,
+                    None => continue,
+                },
+                _ => continue,
+            };
+            let has_positions = indexing_options.index_option().has_positions();
+            let tokenizer = index.tokenizer_for_field(field).map_err(|err| err.to_string())?;
+            for (words, alternatives) in synonyms {
+                let key: Vec<String> = tokenize(&tokenizer, words).into_iter().map(|(_, text)| text).collect();
+                if key.is_empty() {
+                    continue;
+                }
+                let queries = alternatives.iter().filter_map(|alternative|{
+                    let terms: Vec<(usize, Term)> = tokenize(&tokenizer, alternative)
+                        .into_iter()
+                        .map(|(position, text)| (position, Term::from_field_text(field, &text)))
+                        .collect();
+                    terms_query(terms, has_positions)
+                });
+                expansions.entry((field, key)).or_default().extend(queries);
+            }
+        }
+        Ok(SynonymExpander { expansions })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.expansions.is_empty()
+    }
+
+    fn alternatives(&self, leaf: &dyn Query) -> Option<&Vec<Box<dyn Query>>> {
+        let (field, terms) = if let Some(term_query) = leaf.downcast_ref::<TermQuery>() {
+            (term_query.term().field(), vec![term_query.term().clone()])
+        } else if let Some(phrase_query) = leaf.downcast_ref::<PhraseQuery>() {
+            (phrase_query.field(), phrase_query.phrase_terms())
+        } else {
+            return None;
+        };
+        let key = terms.iter().map(|term| term.as_str().map(str::to_string)).collect::<Option<Vec<String>>>()?;
+        self.expansions.get(&(field, key))
+    }
+
+    // `query` or one of the alternatives of the term or the phrase of `leaf`, `query` being `leaf` possibly rewritten
+    pub fn expand(&self, leaf: &dyn Query, query: Box<dyn Query>) -> Box<dyn Query> {
+        match self.alternatives(leaf) {
+            Some(alternatives) => either(query, alternatives),
+            None => query,
+        }
+    }
+
+    // Adds the alternatives of the multi-word synonyms to the consecutive words of the query string that spell them,
+    // `query` being the parsed query and `rewritten` the same query with its leaves rewritten by `rewrite_leaves`.
+    // With `OR` between the words the alternatives are added as optional clauses, with `AND` they replace the words.
+    pub fn expand_words(&self, query: &dyn Query, rewritten: Box<dyn Query>) -> Box<dyn Query> {
+        let (boolean_query, rewritten_query) = match (query.downcast_ref::<BooleanQuery>(), rewritten.downcast_ref::<BooleanQuery>()) {
+            (Some(boolean_query), Some(rewritten_query)) => (boolean_query, rewritten_query),
+            _ => return rewritten,
+        };
+        let clauses: Vec<(Occur, Box<dyn Query>)> = boolean_query.clauses()
+            .iter()
+            .zip(rewritten_query.clauses())
+            .map(|((_, sub_query), (occur, rewritten_sub_query))| (*occur, self.expand_words(sub_query.as_ref(), rewritten_sub_query.box_clone())))
+            .collect();
+        let words: Vec<Vec<(Field, String)>> = boolean_query.clauses().iter().map(|(_, sub_query)| word_terms(sub_query.as_ref())).collect();
+
+        let mut expanded_clauses = Vec::new();
+        let mut optional_clauses = Vec::new();
+        let mut start = 0;
+        while start < clauses.len() {
+            match self.words_alternatives(&clauses, &words, start) {
+                Some((end, alternatives)) if clauses[start].0 == Occur::Must => {
+                    let words_query: Box<dyn Query> = Box::new(BooleanQuery::new(clauses[start..end].iter().map(|(occur, query)| (*occur, query.box_clone())).collect()));
+                    expanded_clauses.push((Occur::Must, either(words_query, alternatives)));
+                    start = end;
+                },
+                Some((end, alternatives)) => {
+                    expanded_clauses.extend(clauses[start..end].iter().map(|(occur, query)| (*occur, query.box_clone())));
+                    optional_clauses.extend(alternatives.iter().map(|alternative| (Occur::Should, alternative.box_clone())));
+                    start = end;
+                },
+                None => {
+                    expanded_clauses.push((clauses[start].0, clauses[start].1.box_clone()));
+                    start += 1;
+                },
+            }
+        }
+        expanded_clauses.extend(optional_clauses);
+        Box::new(BooleanQuery::new(expanded_clauses))
+    }
+
+    // The longest multi-word synonym spelled by the words from `start`, on one field, and the end of its words
+    fn words_alternatives(&self, clauses: &[(Occur, Box<dyn Query>)], words: &[Vec<(Field, String)>], start: usize) -> Option<(usize, &Vec<Box<dyn Query>>)> {
+        let occur = clauses[start].0;
+        if occur == Occur::MustNot {
+            return None;
+        }
+        let same_occur = clauses[start..].iter().take_while(|(clause_occur, _)| *clause_occur == occur).count();
+        (start + 2..=start + same_occur).rev().find_map(|end| {
+            words[start].iter().find_map(|(field, _)| {
+                let key = words[start..end].iter()
+                    .map(|terms| terms.iter().find(|(term_field, _)| term_field == field).map(|(_, text)| text.clone()))
+                    .collect::<Option<Vec<String>>>()?;
+                self.expansions.get(&(*field, key)).map(|alternatives| (end, alternatives))
+            })
+        })
+    }
+}
+
+// The terms a word of the query string is parsed to: a term query, or one term query per default field
+fn word_terms(query: &dyn Query) -> Vec<(Field, String)> {
+    let term_text = |query: &dyn Query| {
+        let term = query.downcast_ref::<TermQuery>()?.term();
+        Some((term.field(), term.as_str()?.to_string()))
+    };
+    if let Some(boolean_query) = query.downcast_ref::<BooleanQuery>() {
+        let terms = boolean_query.clauses()
+            .iter()
+            .map(|(occur, sub_query)| if *occur == Occur::Should { term_text(sub_query.as_ref()) } else { None })
+            .collect::<Option<Vec<(Field, String)>>>()
+            .unwrap_or_default();
+        // several words on a single field are a group of words, not a word
+        let mut fields: Vec<Field> = terms.iter().map(|(field, _)| *field).collect();
+        fields.sort_unstable();
+        fields.dedup();
+        return if fields.len() == terms.len() { terms } else { Vec::new() };
+    }
+    term_text(query).into_iter().collect()
+}
+
+// `query` or one of the `alternatives`
+fn either(query: Box<dyn Query>, alternatives: &[Box<dyn Query>]) -> Box<dyn Query> {
+    let clauses = std::iter::once(query)
+        .chain(alternatives.iter().map(|alternative| alternative.box_clone()))
+        .map(|query| (Occur::Should, query))
+        .collect();
+    Box::new(BooleanQuery::new(clauses))
+}
+
+// A multi-word alternative is a phrase, or all its words when the field has no positions
+fn terms_query(mut terms: Vec<(usize, Term)>, has_positions: bool) -> Option<Box<dyn Query>> {
+    match terms.len() {
+        0 => None,
+        1 => Some(Box::new(TermQuery::new(terms.remove(0).1, IndexRecordOption::WithFreqs))),
+        _ if has_positions => Some(Box::new(PhraseQuery::new_with_offset(terms))),
+        _ => Some(Box::new(BooleanQuery::new(terms.into_iter().map(|(_, term)|{
+            let query: Box<dyn Query> = Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs));
+            (Occur::Must, query)
+        }).collect()))),
+    }
+}
+
+// Levenshtein automatons are only built up to this distance
+pub const MAX_FUZZY_DISTANCE: u8 = 2;
+
+// A `word~N` term of a query string, N being the maximum edit distance
+#[derive(Debug, PartialEq)]
+pub struct FuzzyWord {
+    pub word: String,
+    pub distance: u8,
+    // byte range of the removed `~N` in the query string
+    pub marker: Range<usize>,
+}
+
+pub fn tokenize(tokenizer: &TextAnalyzer, text: &str) -> Vec<(usize, String)> {
+    let mut tokens = Vec::new();
+    tokenizer.token_stream(text).process(&mut |token| tokens.push((token.position, token.text.clone())));
+    tokens
+}
+
+impl FuzzyWord {
+    // The word is compared to the terms of the parsed query once tokenized like the text of their field,
+    // e.g. lowercased or stemmed
+    pub fn matches(&self, tokenizer: &TextAnalyzer, text: &str) -> bool {
+        tokenize(tokenizer, &self.word).last().map_or(false, |(_, token)| token == text)
+    }
+}
+
+const WORD_DELIMITERS: &str = "()[]{}:+^";
+
+// The QueryParser doesn't support `word~N` on terms, only the `"some phrase"~N` slop on phrases:
+// removes the `~N` suffixes outside of phrases and returns the words they applied to.
+pub fn extract_fuzzy_words(query: &str) -> (String, Vec<FuzzyWord>) {
+    let mut cleaned_query = String::with_capacity(query.len());
+    let mut fuzzy_words = Vec::new();
+    let mut in_quotes = false;
+    let mut word_start = 0;
+    let mut chars = query.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        let is_fuzzy_marker = c == '~'
+            && !in_quotes
+            && word_start < cleaned_query.len()
+            && chars.peek().map_or(false, |(_, next)| next.is_ascii_digit());
+        if is_fuzzy_marker {
+            let mut distance = String::new();
+            while let Some((_, digit)) = chars.next_if(|(_, next)| next.is_ascii_digit()) {
+                distance.push(digit);
+            }
+            let word = cleaned_query[word_start..].trim_start_matches('-');
+            fuzzy_words.push(FuzzyWord {
+                word: word.to_string(),
+                distance: distance.parse().unwrap_or(u8::MAX),
+                marker: offset..offset + 1 + distance.len(),
+            });
+            continue;
+        }
+
+        cleaned_query.push(c);
+        if c == '"' {
+            in_quotes = !in_quotes;
+        }
+        // the slop of a phrase follows its closing quote, it must not be taken for a fuzzy word
+        if c == '"' || c.is_whitespace() || WORD_DELIMITERS.contains(c) {
+            word_start = cleaned_query.len();
+        }
+    }
+    (cleaned_query, fuzzy_words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_fuzzy_words, FuzzyWord};
+
+    #[test]
+    fn fuzzy_words() {
+        let (query, fuzzy_words) = extract_fuzzy_words(r#"Recieve~1 AND title:adress~2 "old man"~1 -sae~1 lunch"#);
+        assert_eq!(r#"Recieve AND title:adress "old man"~1 -sae lunch"#, query);
+        assert_eq!(vec![
+            FuzzyWord{word: "Recieve".to_string(), distance: 1, marker: 7..9},
+            FuzzyWord{word: "adress".to_string(), distance: 2, marker: 26..28},
+            FuzzyWord{word: "sae".to_string(), distance: 1, marker: 45..47},
+        ], fuzzy_words);
+
+        let (query, fuzzy_words) = extract_fuzzy_words(r#""in~1 quotes" ~2"#);
+        assert_eq!(r#""in~1 quotes" ~2"#, query);
+        assert!(fuzzy_words.is_empty());
+    }
+}
@@ -0,0 +1,261 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::{Serialize, Deserialize};
+use log::warn;
+
+use tantivy::{
+    directory::error::OpenReadError,
+    tokenizer::{
+        Token, TokenStream, AsciiFoldingFilter, Language, LowerCaser, NgramTokenizer, RawTokenizer, RemoveLongFilter, SimpleTokenizer,
+        Stemmer, StopWordFilter, TextAnalyzer, WhitespaceTokenizer,
+    },
+    Directory, Index as TantivyIndex,
+};
+
+use crate::cjk_tokenizer::CjkTokenizer;
+use crate::js_tokenizer::{JsTokenizer, registered_js_tokenizers, take_tokenizer_error};
+use crate::errors::WasmInterfaceError;
+use crate::query_dsl::get_text_field;
+use crate::utils::utf16_len;
+
+// The tokenizers registered by default by tantivy: `default`, `raw`, `en_stem` and `whitespace`.
+// The stemmers of the other languages are registered under the same `<code>_stem` naming, and `cjk` for Chinese, Japanese and Korean.
+const STEMMERS: [(&str, Language); 17] = [
+    ("ar_stem", Language::Arabic),
+    ("da_stem", Language::Danish),
+    ("de_stem", Language::German),
+    ("el_stem", Language::Greek),
+    ("es_stem", Language::Spanish),
+    ("fi_stem", Language::Finnish),
+    ("fr_stem", Language::French),
+    ("hu_stem", Language::Hungarian),
+    ("it_stem", Language::Italian),
+    ("nl_stem", Language::Dutch),
+    ("no_stem", Language::Norwegian),
+    ("pt_stem", Language::Portuguese),
+    ("ro_stem", Language::Romanian),
+    ("ru_stem", Language::Russian),
+    ("sv_stem", Language::Swedish),
+    ("ta_stem", Language::Tamil),
+    ("tr_stem", Language::Turkish),
+];
+
+pub fn is_builtin_tokenizer(name: &str) -> bool {
+    ["default", "raw", "en_stem", "whitespace", "cjk"].contains(&name) || STEMMERS.iter().any(|(stemmer, _)| *stemmer == name)
+}
+
+// The stream of the tokenizers that compute all the tokens of a text upfront
+pub struct VecTokenStream {
+    tokens: Vec<Token>,
+    index: usize,
+}
+
+impl From<Vec<Token>> for VecTokenStream {
+    fn from(tokens: Vec<Token>) -> Self {
+        VecTokenStream { tokens, index: 0 }
+    }
+}
+
+impl TokenStream for VecTokenStream {
+    fn advance(&mut self) -> bool {
+        self.index += 1;
+        self.index <= self.tokens.len()
+    }
+
+    fn token(&self) -> &Token {
+        &self.tokens[self.index - 1]
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.tokens[self.index - 1]
+    }
+}
+
+// The definitions of the custom analyzers are stored next to `meta.json`, so that they travel with the exported segments
+pub const ANALYZERS_FILE: &str = "analyzers.json";
+
+pub type Analyzers = HashMap<String, AnalyzerDefinition>;
+
+// A custom analyzer: a tokenizer followed by filters applied in order, e.g.
+// `{tokenizer: {ngram: {minGram: 2, maxGram: 3}}, filters: ["lowercase", "asciiFolding"]}`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AnalyzerDefinition {
+    tokenizer: TokenizerDefinition,
+    filters: Option<Vec<FilterDefinition>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum TokenizerDefinition {
+    // splits on the characters that are not alphanumeric, like the `default` tokenizer
+    Simple,
+    Whitespace,
+    // the whole text is a single token
+    Raw,
+    Ngram(NgramDefinition),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct NgramDefinition {
+    min_gram: usize,
+    max_gram: usize,
+    // only the n-grams starting at the beginning of the text, for search-as-you-type
+    prefix_only: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum FilterDefinition {
+    Lowercase,
+    // replaces the accented characters by their ASCII equivalent
+    AsciiFolding,
+    StopWords(Vec<String>),
+    // removes the tokens longer than the given number of bytes
+    RemoveLong(usize),
+}
+
+impl AnalyzerDefinition {
+    fn build(&self, name: &str) -> Result<TextAnalyzer, WasmInterfaceError> {
+        let mut analyzer = match &self.tokenizer {
+            TokenizerDefinition::Simple => TextAnalyzer::from(SimpleTokenizer),
+            TokenizerDefinition::Whitespace => TextAnalyzer::from(WhitespaceTokenizer),
+            TokenizerDefinition::Raw => TextAnalyzer::from(RawTokenizer),
+            TokenizerDefinition::Ngram(ngram) => {
+                if ngram.min_gram == 0 || ngram.min_gram > ngram.max_gram {
+                    return Err(WasmInterfaceError::InvalidAnalyzer(name.to_string(), "`minGram` must be between 1 and `maxGram`".to_string()));
+                }
+                TextAnalyzer::from(NgramTokenizer::new(ngram.min_gram, ngram.max_gram, ngram.prefix_only.unwrap_or_default()))
+            },
+        };
+        for filter in self.filters.iter().flatten() {
+            analyzer = match filter {
+                FilterDefinition::Lowercase => analyzer.filter(LowerCaser),
+                FilterDefinition::AsciiFolding => analyzer.filter(AsciiFoldingFilter),
+                FilterDefinition::StopWords(words) => analyzer.filter(StopWordFilter::remove(words.clone())),
+                FilterDefinition::RemoveLong(limit) => analyzer.filter(RemoveLongFilter::limit(*limit)),
+            };
+        }
+        Ok(analyzer)
+    }
+}
+
+pub fn read_analyzers(directory: &dyn Directory) -> Result<Analyzers, String> {
+    match directory.atomic_read(Path::new(ANALYZERS_FILE)) {
+        Ok(data) => serde_json::from_slice(&data).map_err(|err| err.to_string()),
+        Err(OpenReadError::FileDoesNotExist(_)) => Ok(Analyzers::new()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+pub fn write_analyzers(directory: &dyn Directory, analyzers: &Analyzers) -> Result<(), String> {
+    let data = serde_json::to_vec(analyzers).map_err(|err| err.to_string())?;
+    directory.atomic_write(Path::new(ANALYZERS_FILE), &data).map_err(|err| err.to_string())
+}
+
+// Adds the analyzers of `from` to the ones of `into`, an analyzer name must have the same definition in both
+pub fn merge_analyzers(into: &dyn Directory, from: &dyn Directory) -> Result<(), String> {
+    let mut analyzers = read_analyzers(into)?;
+    let analyzers_to_add = read_analyzers(from)?;
+    if analyzers_to_add.is_empty() {
+        return Ok(());
+    }
+    for (name, definition) in analyzers_to_add {
+        match analyzers.get(&name) {
+            Some(existing) if *existing != definition => {
+                return Err(WasmInterfaceError::ConflictingAnalyzer(name).to_string());
+            },
+            Some(_) => {},
+            None => {
+                analyzers.insert(name, definition);
+            },
+        }
+    }
+    write_analyzers(into, &analyzers)
+}
+
+// Tokenizers are not persisted with the index: they must be registered on every index opened on a directory
+// whose schema may reference them, before indexing, searching or merging.
+pub fn register_tokenizers(index: &TantivyIndex) -> Result<(), String> {
+    let tokenizers = index.tokenizers();
+    for (name, language) in STEMMERS {
+        // same pipeline as `en_stem`
+        let analyzer = TextAnalyzer::from(SimpleTokenizer)
+            .filter(RemoveLongFilter::limit(40))
+            .filter(LowerCaser)
+            .filter(Stemmer::new(language));
+        tokenizers.register(name, analyzer);
+    }
+    tokenizers.register("cjk", TextAnalyzer::from(CjkTokenizer).filter(RemoveLongFilter::limit(40)).filter(LowerCaser));
+    for name in registered_js_tokenizers() {
+        tokenizers.register(&name, JsTokenizer { name: name.clone() });
+    }
+    for (name, definition) in read_analyzers(index.directory())? {
+        if is_builtin_tokenizer(&name) {
+            return Err(WasmInterfaceError::TokenizerNameTaken(name).to_string());
+        }
+        // the segments were indexed with their analyzers: they take precedence over the JS tokenizers of the same name
+        if tokenizers.get(&name).is_some() {
+            warn!("The analyzer `{}` of the segments replaces the JS tokenizer of the same name", name);
+        }
+        tokenizers.register(&name, definition.build(&name).map_err(|err| err.to_string())?);
+    }
+    Ok(())
+}
+
+// A token produced by the tokenizer of a field, its text is the term indexed or searched
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalyzedToken {
+    pub text: String,
+    // offsets in the analyzed text
+    pub offset_from: usize,
+    pub offset_to: usize,
+    pub position: usize,
+}
+
+pub fn analyze(index: &TantivyIndex, field_name: &str, text: &str) -> Result<Vec<AnalyzedToken>, String> {
+    let field = get_text_field(index, field_name)?;
+    let tokenizer = index.tokenizer_for_field(field).map_err(|err| err.to_string())?;
+    let mut tokens = Vec::new();
+    take_tokenizer_error();
+    tokenizer.token_stream(text).process(&mut |token| {
+        tokens.push(AnalyzedToken {
+            text: token.text.clone(),
+            offset_from: utf16_len(&text[..token.offset_from]),
+            offset_to: utf16_len(&text[..token.offset_to]),
+            position: token.position,
+        });
+    });
+    match take_tokenizer_error() {
+        Some(err) => Err(err),
+        None => Ok(tokens),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::tokenizer::TokenStream;
+
+    use super::AnalyzerDefinition;
+
+    fn tokens(definition: &str, text: &str) -> Vec<String> {
+        let definition: AnalyzerDefinition = serde_json::from_str(definition).unwrap();
+        let analyzer = definition.build("test").unwrap();
+        let mut tokens = Vec::new();
+        analyzer.token_stream(text).process(&mut |token| tokens.push(token.text.clone()));
+        tokens
+    }
+
+    #[test]
+    fn analyzers() {
+        assert_eq!(vec!["francois", "muller"], tokens(r#"{"tokenizer": "simple", "filters": ["lowercase", "asciiFolding", {"stopWords": ["and"]}]}"#, "François and Müller"));
+        assert_eq!(vec!["ch", "che", "he", "hen", "en"], tokens(r#"{"tokenizer": {"ngram": {"minGram": 2, "maxGram": 3}}, "filters": ["lowercase"]}"#, "Chen"));
+        assert_eq!(vec!["Ch", "Che"], tokens(r#"{"tokenizer": {"ngram": {"minGram": 2, "maxGram": 3, "prefixOnly": true}}}"#, "Chen"));
+        assert_eq!(vec!["a"], tokens(r#"{"tokenizer": "whitespace", "filters": [{"removeLong": 3}]}"#, "a verylongword"));
+
+        let definition: AnalyzerDefinition = serde_json::from_str(r#"{"tokenizer": {"ngram": {"minGram": 3, "maxGram": 2}}}"#).unwrap();
+        assert!(definition.build("test").is_err());
+    }
+}
@@ -4,3 +4,8 @@ use wasm_bindgen::prelude::*;
 pub fn set_panic_hook() {
     console_error_panic_hook::set_once();
 }
+
+// Length of a string for JS, whose string indexes count UTF-16 code units
+pub fn utf16_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
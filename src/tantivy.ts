@@ -4,6 +4,7 @@ import init, {
   SearchIndex as WasmSearchIndex,
   SegmentBuilder as WasmSegmentBuilder,
   Segment as WasmSegment,
+  registerTokenizer as wasmRegisterTokenizer,
   set_panic_hook
 } from "./pkg/tantivy_js";
 
@@ -35,27 +36,128 @@ export const initialize = async (options?: TantivyLoadOptions) => {
   return;
 };
 
+export type FieldType = 'u64' | 'i64' | 'f64' | 'date' | 'facet';
+
+export type Tokenizer = 'default' | 'raw' | 'whitespace' | 'cjk' | 'en_stem' | 'ar_stem' | 'da_stem' | 'de_stem' | 'el_stem' | 'es_stem'
+  | 'fi_stem' | 'fr_stem' | 'hu_stem' | 'it_stem' | 'nl_stem' | 'no_stem' | 'pt_stem' | 'ro_stem' | 'ru_stem' | 'sv_stem' | 'ta_stem' | 'tr_stem';
+
 export type IndexSchema<Fields extends string> = {
   [field in Fields]: {
+    /**
+     * Type of the values of the field, fields without a type are text fields
+     */
+    type?: FieldType;
     string?: boolean;
     text?: boolean;
     stored?: boolean;
+    /**
+     * Store the values in a column-oriented fast field, only for typed fields and `string` fields
+     */
+    fast?: boolean;
+    /**
+     * Index the values so they can be searched, defaults to true for typed fields
+     */
+    indexed?: boolean;
+    /**
+     * Tokenizer of a `text` field, defaults to `default`. The `<language>_stem` tokenizers also reduce the words to their stem,
+     * `cjk` splits Chinese, Japanese and Korean text into single characters and bigrams, no dictionary is needed
+     */
+    tokenizer?: Tokenizer | string;
+  }
+}
+
+export type Token = {
+  /**
+   * Defaults to the text between the offsets
+   */
+  text?: string;
+  /**
+   * Indexes in the tokenized string
+   */
+  offsetFrom: number;
+  offsetTo: number;
+  /**
+   * Position of the token for phrase queries, defaults to the position of the previous token plus one
+   */
+  position?: number;
+}
+
+/**
+ * Register a tokenizer implemented in JS, to use as the `tokenizer` of text fields.
+ * It must be registered before building, searching or merging the segments that use it.
+ * When it throws or returns invalid tokens, `addDocument` and the searches using it throw.
+ * `addDocument` still indexes the document, without the terms of the text that failed.
+ * The custom analyzers stored with the segments take precedence over a tokenizer of the same name.
+ */
+export const registerTokenizer = (name: string, tokenizer: (text: string) => Token[]) => {
+  wasmRegisterTokenizer(name, tokenizer);
+};
+
+export type AnalyzedToken = {
+  /**
+   * The term indexed or searched
+   */
+  text: string;
+  offsetFrom: number;
+  offsetTo: number;
+  position: number;
+}
+
+export type AnalyzerFilter = 'lowercase' | 'asciiFolding' | {stopWords: string[]} | {removeLong: number};
+
+/**
+ * Custom analyzers by name, usable as the `tokenizer` of text fields. Their definitions are stored in the exported segments
+ */
+export type Analyzers = {
+  [name: string]: {
+    tokenizer: 'simple' | 'whitespace' | 'raw' | {ngram: {minGram: number, maxGram: number, prefixOnly?: boolean}};
+    /**
+     * Applied in order to the tokens
+     */
+    filters?: AnalyzerFilter[];
   }
 }
 
+/**
+ * Dates can be given as Date objects, ISO-8601 strings (RFC 3339 date-times, or dates alone for midnight UTC) or milliseconds since the epoch
+ */
+export type FieldValue = string | number | Date;
+
+const toWasmFieldValue = (value: FieldValue) => value instanceof Date ? value.getTime() : value;
+
+/**
+ * A document field can hold several values, except fast fields
+ */
+export type DocumentValue = FieldValue | FieldValue[];
+
 // Free up the Rust memory when the SegmentBuilder class is not used anymore
 const segmentBuildersFinalizationRegistry = new FinalizationRegistry<WasmSegmentBuilder>((wasmSegmentBuilder) => wasmSegmentBuilder.free());
 
 export class SegmentBuilder<Fields extends string> {
   private wasmSegmentBuilder: WasmSegmentBuilder;
 
-  constructor(schema: IndexSchema<Fields>, memoryArenaNumBytes = 50_000_000) {
-    this.wasmSegmentBuilder = new WasmSegmentBuilder(schema, memoryArenaNumBytes);
+  constructor(schema: IndexSchema<Fields>, memoryArenaNumBytes = 50_000_000, analyzers: Analyzers = {}) {
+    this.wasmSegmentBuilder = new WasmSegmentBuilder(schema, memoryArenaNumBytes, analyzers);
     segmentBuildersFinalizationRegistry.register(this, this.wasmSegmentBuilder);
   }
 
-  addDocument(document: {[field in Fields]: string}) {
-    this.wasmSegmentBuilder.addDocument(document);
+  addDocument(document: {[field in Fields]?: DocumentValue}) {
+    const wasmDocument: {[field: string]: string | number | (string | number)[]} = {};
+    for (const [field, value] of Object.entries<DocumentValue | undefined>(document)) {
+      if (Array.isArray(value)) {
+        wasmDocument[field] = value.map(toWasmFieldValue);
+      } else if (value !== undefined) {
+        wasmDocument[field] = toWasmFieldValue(value);
+      }
+    }
+    this.wasmSegmentBuilder.addDocument(wasmDocument);
+  }
+
+  /**
+   * Run the tokenizer of a text field on a text, to see the terms that get indexed
+   */
+  analyze(field: Fields, text: string): AnalyzedToken[] {
+    return this.wasmSegmentBuilder.analyze(field, text);
   }
 
   removeDocuments() {
@@ -68,6 +170,236 @@ export class SegmentBuilder<Fields extends string> {
   }
 }
 
+/**
+ * Structured query, an alternative to the query string syntax that doesn't need escaping
+ */
+export type QueryDsl<Fields extends string> =
+  | {bool: {must?: QueryDsl<Fields>[], should?: QueryDsl<Fields>[], mustNot?: QueryDsl<Fields>[], boost?: number}}
+  /** Exact term, the value is not tokenized */
+  | {term: {field: Fields, value: string | number, boost?: number}}
+  /** The value is tokenized with the tokenizer of the field */
+  | {phrase: {field: Fields, value: string, slop?: number, boost?: number}}
+  | {range: {field: Fields, gt?: string | number, gte?: string | number, lt?: string | number, lte?: string | number, boost?: number}}
+  /** A single word, lowercased or stemmed like the indexed text of the field */
+  | {prefix: {field: Fields, value: string, boost?: number}}
+  | {fuzzy: {field: Fields, value: string, distance?: number, transpositions?: boolean, prefix?: boolean, boost?: number}}
+  /**
+   * The pattern must match a whole indexed term, e.g. `INV-20[0-9]{2}-.*` on a `string` field
+   */
+  | {regex: {field: Fields, pattern: string, boost?: number}}
+  /**
+   * `*` matches any sequence of characters and `?` a single character, e.g. `INV-2022-*`
+   */
+  | {wildcard: {field: Fields, value: string, boost?: number}}
+  | {all: {boost?: number}};
+
+export type Query<Fields extends string> = string | QueryDsl<Fields>;
+
+export type SearchOptions<Fields extends string> = {
+  /**
+   * Maximum number of hits, defaults to 10
+   */
+  limit?: number;
+  /**
+   * Number of hits to skip
+   */
+  offset?: number;
+  /**
+   * Resume the search after the last hit of a previous search, using the `cursor` of its result
+   */
+  cursor?: string;
+  fields?: Fields[];
+  /**
+   * Sort the hits by a numeric or date fast field instead of relevance, cannot be combined with `cursor`
+   */
+  sortBy?: {field: Fields, order?: 'asc' | 'desc'};
+  /**
+   * Generate snippets of stored text fields with the matched terms highlighted
+   */
+  highlight?: {
+    fields: Fields[];
+    /**
+     * Maximum length of a snippet, defaults to 150
+     */
+    maxChars?: number;
+    /**
+     * Inserted before each matched term in the HTML snippet, defaults to `<b>`
+     */
+    preTag?: string;
+    /**
+     * Inserted after each matched term in the HTML snippet, defaults to `</b>`
+     */
+    postTag?: string;
+  };
+  /**
+   * Facet paths (e.g. `/` or `/inbox`) whose children are counted in the matching documents, by facet field
+   */
+  facets?: {[field in Fields]?: string[]};
+  /**
+   * Elasticsearch-compatible aggregations over the fast fields of the matching documents, by name, e.g.
+   * `{senders: {terms: {field: 'sender'}}, sizes: {histogram: {field: 'size', interval: 1000}}}`.
+   * The supported aggregations are `terms`, `histogram`, `range`, `average`, `min`, `max`, `sum` and `stats`
+   */
+  aggregations?: {[name: string]: object};
+  /**
+   * Multiply the scores of the matches of a query string in a field, e.g. `{title: 2}` to rank title matches higher
+   */
+  boosts?: {[field in Fields]?: number};
+  /**
+   * Require all the words of a query string to match instead of any of them, defaults to false
+   */
+  conjunction?: boolean;
+  /**
+   * Search with the parts of a query string that can be parsed instead of throwing a `QueryError`,
+   * the skipped errors are returned in the `errors` of the result
+   */
+  lenient?: boolean;
+  /**
+   * Match the terms of a query string on `fields` with typos, `word~N` in the query string sets the distance of a single word
+   */
+  fuzzy?: {
+    /**
+     * Maximum Levenshtein distance, from 0 to 2, defaults to 1
+     */
+    distance?: number;
+    /**
+     * Count a transposition of two adjacent characters as a single edit, defaults to true
+     */
+    transpositions?: boolean;
+    /**
+     * Match the terms starting with a word at the given distance, for search-as-you-type
+     */
+    prefix?: boolean;
+  };
+}
+
+export type Highlight = {
+  /**
+   * HTML-safe snippet with the matched terms surrounded by the highlight tags
+   */
+  html: string;
+  /**
+   * Raw text of the snippet
+   */
+  fragment: string;
+  /**
+   * Positions of the matched terms in `fragment`
+   */
+  ranges: {start: number, end: number}[];
+}
+
+export type SearchHit<Fields extends string> = {
+  /**
+   * Relevance score (BM25) of the document for the query
+   */
+  score: number;
+  /**
+   * Id of the segment containing the document, `segmentId` and `docId` together identify a document in a SearchIndex
+   */
+  segmentId: string;
+  /**
+   * Id of the document in its segment
+   */
+  docId: number;
+  /**
+   * Stored fields of the document, every value of a field is returned
+   */
+  doc: {[field in Fields]?: (string | number)[]};
+  /**
+   * Value of the `sortBy` field, dates are ISO-8601 strings
+   */
+  sortValue?: string | number;
+  /**
+   * Snippets of the fields requested in the `highlight` option
+   */
+  highlights?: {[field in Fields]?: Highlight};
+}
+
+/**
+ * An invalid query string, thrown by the searches unless they are `lenient`
+ */
+export type QueryError = {
+  kind: 'syntax' | 'unknownField' | 'invalidField' | 'invalidValue' | 'unsupported';
+  message: string;
+  /**
+   * Index of the error in the query string, when it can be located
+   */
+  position?: number;
+}
+
+export type SearchResult<Fields extends string> = {
+  hits: SearchHit<Fields>[];
+  /**
+   * Number of matching documents for each child of the requested facets, by facet field
+   */
+  facets?: {[field in Fields]?: {[facet: string]: number}};
+  /**
+   * Results of the `aggregations` option by name, in the Elasticsearch format, e.g. `{senders: {buckets: [{key: 'jane', doc_count: 3}]}}`
+   */
+  aggregations?: {[name: string]: any};
+  /**
+   * Opaque token identifying the last hit, to pass as the `cursor` option to fetch the next page
+   */
+  cursor?: string;
+  /**
+   * Errors of the query string skipped in `lenient` mode
+   */
+  errors?: QueryError[];
+}
+
+/**
+ * Identifies an indexed document, from the `segmentId` and `docId` of a hit
+ */
+export type HitRef = {
+  segmentId: string;
+  docId: number;
+}
+
+/**
+ * How a score is computed: the value is the result of combining the values of the details
+ */
+export type Explanation = {
+  value: number;
+  description: string;
+  details?: Explanation[];
+  context?: string[];
+}
+
+export type MoreLikeThisOptions<Fields extends string> = {
+  /**
+   * Text fields whose terms are compared, defaults to all the indexed text fields
+   */
+  fields?: Fields[];
+  /**
+   * Maximum number of hits, defaults to 10
+   */
+  limit?: number;
+  offset?: number;
+  /**
+   * Terms appearing fewer times in the source are ignored, defaults to 2
+   */
+  minTermFrequency?: number;
+  /**
+   * Terms appearing in fewer documents are ignored, defaults to 5
+   */
+  minDocFrequency?: number;
+  maxDocFrequency?: number;
+  /**
+   * Number of terms of the source with the best scores kept in the query, defaults to 25
+   */
+  maxQueryTerms?: number;
+  minWordLength?: number;
+  maxWordLength?: number;
+}
+
+export type Suggestion = {
+  term: string;
+  /**
+   * Number of documents containing the term, deleted documents included
+   */
+  docFreq: number;
+}
+
 // Free up the Rust memory when the SearchIndex class is not used anymore
 const searchIndexFinalizationRegistry = new FinalizationRegistry<WasmSearchIndex>((wasmSearchIndex) => wasmSearchIndex.free());
 export class SearchIndex<Fields extends string> {
@@ -86,8 +418,63 @@ export class SearchIndex<Fields extends string> {
     this.wasmSearchIndex.removeSegment(segment._getWasmSegment());
   }
 
-  search(query: string, options: {limit?: number, fields?: (Fields)[] } = {}): {[field in Fields]: string}[] {
-    return this.wasmSearchIndex.search(query, options);
+  /**
+   * Replace the synonyms expanded in the query strings, e.g. `{invoice: ['bill', 'receipt', 'credit note']}`.
+   * Words and alternatives can be phrases, a phrase is matched quoted or as consecutive words of the query string.
+   * A word doesn't match its alternatives the other way around unless they are listed too
+   */
+  setSynonyms(synonyms: {[word: string]: string[]}) {
+    this.wasmSearchIndex.setSynonyms(synonyms);
+  }
+
+  /**
+   * Throws a `QueryError` object when the query string is invalid, unless the search is `lenient`
+   */
+  search(query: Query<Fields>, options: SearchOptions<Fields> = {}): SearchResult<Fields> {
+    return this.wasmSearchIndex.search(query, {limit: 10, ...options});
+  }
+
+  /**
+   * Explain the score of a hit for a query, the options are the ones of the search that returned the hit
+   */
+  explain(query: Query<Fields>, hitRef: HitRef, options: Pick<SearchOptions<Fields>, 'fields' | 'fuzzy' | 'boosts' | 'conjunction' | 'lenient'> = {}): Explanation {
+    return this.wasmSearchIndex.explain(query, {segmentId: hitRef.segmentId, docId: hitRef.docId}, options);
+  }
+
+  /**
+   * Find the documents similar to an indexed document or to some field values. The terms of an indexed document
+   * are taken from its stored fields, the document itself is not part of the hits
+   */
+  moreLikeThis(source: HitRef | {[field in Fields]?: string | string[]}, options: MoreLikeThisOptions<Fields> = {}): SearchResult<Fields> {
+    return this.wasmSearchIndex.moreLikeThis(source, options);
+  }
+
+  /**
+   * Count the documents matching the query without retrieving them
+   */
+  count(query: Query<Fields>, options: Pick<SearchOptions<Fields>, 'fields' | 'fuzzy' | 'conjunction' | 'lenient'> = {}): number {
+    return this.wasmSearchIndex.count(query, options);
+  }
+
+  /**
+   * Check if at least one document matches the query, stops at the first match
+   */
+  exists(query: Query<Fields>, options: Pick<SearchOptions<Fields>, 'fields' | 'fuzzy' | 'conjunction' | 'lenient'> = {}): boolean {
+    return this.wasmSearchIndex.exists(query, options);
+  }
+
+  /**
+   * Most frequent terms of a text field starting with the last word of `prefix`, for autocomplete
+   */
+  suggest(field: Fields, prefix: string, limit = 10): Suggestion[] {
+    return this.wasmSearchIndex.suggest(field, prefix, limit);
+  }
+
+  /**
+   * Run the tokenizer of a text field on a text, to see the terms that get searched
+   */
+  analyze(field: Fields, text: string): AnalyzedToken[] {
+    return this.wasmSearchIndex.analyze(field, text);
   }
 
   directorySummary() {